@@ -0,0 +1,36 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use mail_auth::{Resolver, SpfResult};
+
+#[tokio::main]
+async fn main() {
+    // Create a resolver using Google DNS over TLS
+    let resolver = Resolver::new_google_tls().unwrap();
+
+    // Other public resolvers are also available
+    let _ = Resolver::new_google().unwrap();
+    let _ = Resolver::new_quad9_tls().unwrap();
+    let _ = Resolver::new_quad9().unwrap();
+    let _ = Resolver::new_cloudflare().unwrap();
+
+    // Or use the nameservers configured in the system
+    let _ = Resolver::new_system_conf().unwrap();
+
+    // Verify HELO identity
+    let result = resolver
+        .verify_spf_helo(
+            "127.0.0.1".parse().unwrap(),
+            "gmail.com",
+            "my-host-domain.org",
+        )
+        .await;
+    assert_eq!(result.result(), SpfResult::Fail);
+}
@@ -9,10 +9,7 @@
  */
 
 use crate::{
-    common::{
-        crypto::Algorithm,
-        headers::{HeaderWriter, Writer},
-    },
+    common::headers::{HeaderWriter, Writer},
     dkim::Canonicalization,
     AuthenticationResults,
 };
@@ -29,11 +26,7 @@ impl Signature {
         writer.write(b"i=");
         writer.write(self.i.to_string().as_bytes());
         writer.write(b"; a=");
-        writer.write(match self.a {
-            Algorithm::RsaSha256 => b"rsa-sha256",
-            Algorithm::RsaSha1 => b"rsa-sha1",
-            Algorithm::Ed25519Sha256 => b"ed25519-sha256",
-        });
+        writer.write(self.a.as_str().as_bytes());
         for (tag, value) in [(&b"; s="[..], &self.s), (&b"; d="[..], &self.d)] {
             writer.write(tag);
             writer.write(value.as_bytes());
@@ -110,11 +103,7 @@ impl Seal {
         writer.write(b"i=");
         writer.write(self.i.to_string().as_bytes());
         writer.write(b"; a=");
-        writer.write(match self.a {
-            Algorithm::RsaSha256 => b"rsa-sha256",
-            Algorithm::RsaSha1 => b"rsa-sha1",
-            Algorithm::Ed25519Sha256 => b"ed25519-sha256",
-        });
+        writer.write(self.a.as_str().as_bytes());
         for (tag, value) in [(&b"; s="[..], &self.s), (&b"; d="[..], &self.d)] {
             writer.write(tag);
             writer.write(value.as_bytes());
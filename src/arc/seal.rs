@@ -168,14 +168,15 @@ impl Signature {
         let mut signed_headers = Vec::with_capacity(self.h.len());
 
         for (name, value) in &message.headers {
+            let field_name = name.trim_ascii_end();
             if let Some(pos) = self
                 .h
                 .iter()
-                .position(|header| name.eq_ignore_ascii_case(header.as_bytes()))
+                .position(|header| field_name.eq_ignore_ascii_case(header.as_bytes()))
             {
                 headers.push((*name, *value));
                 found_headers[pos] = true;
-                signed_headers.push(std::str::from_utf8(name).unwrap().into());
+                signed_headers.push(std::str::from_utf8(field_name).unwrap().into());
             }
         }
 
@@ -208,7 +209,7 @@ mod test {
             parse::TxtRecordParser,
             verify::DomainKey,
         },
-        dkim::DkimSigner,
+        dkim::{DkimOutputs, DkimSigner},
         AuthenticatedMessage, AuthenticationResults, DkimResult, Resolver,
     };
 
@@ -305,6 +306,208 @@ mod test {
         //println!("{}", raw_message);
     }
 
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn arc_seal_resigned() {
+        let message = concat!(
+            "From: queso@manchego.org\r\n",
+            "To: cheese-lovers@scamorza.org\r\n",
+            "Subject: Say cheese\r\n",
+            "\r\n",
+            "We need to settle which one of us ",
+            "is tastier.\r\n"
+        );
+
+        // Create resolver
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        {
+            resolver.txt_add(
+                "rsa._domainkey.manchego.org.".to_string(),
+                DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3600, 0),
+            );
+            resolver.txt_add(
+                "ed._domainkey.scamorza.org.".to_string(),
+                DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3600, 0),
+            );
+        }
+
+        // Create private keys
+        let pk_ed_public =
+            base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap();
+        let pk_ed_private = base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap();
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(&pk_ed_private).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(&pk_ed_private, &pk_ed_public).unwrap();
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed_arc = Ed25519Key::from_bytes(&pk_ed_private).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed_arc =
+            Ed25519Key::from_seed_and_public_key(&pk_ed_private, &pk_ed_public).unwrap();
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+
+        // Original message signed by the author's domain
+        let original = DkimSigner::from_key(pk_rsa)
+            .domain("manchego.org")
+            .selector("rsa")
+            .headers(["From", "To", "Subject"])
+            .sign(message.as_bytes())
+            .unwrap()
+            .to_header()
+            + message;
+
+        // The list verifies the original message
+        let original = AuthenticatedMessage::parse(original.as_bytes()).unwrap();
+        let dkim_result = resolver.verify_dkim(&original).await;
+        let arc_result = resolver.verify_arc(&original).await;
+        assert_eq!(dkim_result.pass_domains(), vec!["manchego.org"]);
+        let auth_results = AuthenticationResults::new("scamorza.org")
+            .with_dkim_results(&dkim_result, "manchego.org");
+
+        // Appending a footer breaks the author's signature
+        let footer = b"--\r\nSent to cheese-lovers@scamorza.org\r\n";
+        let modified = [original.raw_message(), footer].concat();
+        let modified = AuthenticatedMessage::parse(&modified).unwrap();
+        assert_eq!(
+            resolver.verify_dkim(&modified).await[0].result(),
+            &DkimResult::Neutral(crate::Error::FailedBodyHashMatch)
+        );
+
+        // Strip the broken signature, re-sign and seal the prior results
+        let resigned = DkimSigner::from_key(pk_ed)
+            .domain("scamorza.org")
+            .selector("ed")
+            .headers(["From", "To", "Subject"])
+            .resign(&modified, |signature| {
+                signature
+                    .as_ref()
+                    .map_or(true, |signature| signature.d == "manchego.org")
+            })
+            .unwrap();
+        let resigned = AuthenticatedMessage::parse(&resigned).unwrap();
+        let arc = ArcSealer::from_key(pk_ed_arc)
+            .domain("scamorza.org")
+            .selector("ed")
+            .headers(["From", "To", "Subject", "DKIM-Signature"])
+            .seal(&resigned, &auth_results, &arc_result)
+            .unwrap();
+        let forwarded = [
+            arc.to_header().as_bytes(),
+            auth_results.to_header().as_bytes(),
+            resigned.raw_message(),
+        ]
+        .concat();
+
+        // Downstream verification
+        let forwarded = AuthenticatedMessage::parse(&forwarded).unwrap();
+        let dkim_result = resolver.verify_dkim(&forwarded).await;
+        assert_eq!(dkim_result.len(), 1);
+        assert_eq!(dkim_result.pass_domains(), vec!["scamorza.org"]);
+        let arc_result = resolver.verify_arc(&forwarded).await;
+        assert_eq!(arc_result.result(), &DkimResult::Pass);
+        assert_eq!(arc_result.sealer_domains(), vec!["scamorza.org"]);
+        assert!(std::str::from_utf8(forwarded.aar_headers[0].value)
+            .unwrap()
+            .contains("dkim=pass header.d=manchego.org"),);
+        assert!(forwarded.raw_body().ends_with(footer));
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn arc_seal_header_order() {
+        let message = concat!(
+            "From: queso@manchego.org\r\n",
+            "To: affumicata@scamorza.org\r\n",
+            "Subject: Say cheese\r\n",
+            "\r\n",
+            "We need to settle which one of us ",
+            "is tastier.\r\n"
+        );
+
+        // Create resolver
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "rsa._domainkey.manchego.org.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        // Seal the message twice
+        let mut raw_message = message.to_string();
+        for _ in 0..2 {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            raw_message =
+                arc_verify_and_seal(&resolver, &raw_message, "manchego.org", "rsa", pk_rsa).await;
+        }
+        let first_set = raw_message.find("ARC-Seal: i=1;").unwrap();
+        let original = raw_message.find("From: ").unwrap();
+
+        // The seal only covers ARC headers, which are sorted by instance
+        // rather than by their position in the message
+        for (test, raw_message, expected) in [
+            ("Known-good seal", raw_message.clone(), DkimResult::Pass),
+            (
+                "Sets in reverse order",
+                format!(
+                    "{}{}{}",
+                    &raw_message[first_set..original],
+                    &raw_message[..first_set],
+                    &raw_message[original..]
+                ),
+                DkimResult::Pass,
+            ),
+            (
+                "Reordered tags in a prior ARC-Seal",
+                raw_message.replacen(
+                    "ARC-Seal: i=1; a=rsa-sha256; s=rsa; d=manchego.org;",
+                    "ARC-Seal: i=1; a=rsa-sha256; d=manchego.org; s=rsa;",
+                    1,
+                ),
+                DkimResult::Fail(crate::Error::FailedVerification),
+            ),
+            (
+                "Reordered tags in a prior ARC-Message-Signature",
+                raw_message.replacen(
+                    "ARC-Message-Signature: i=1; a=rsa-sha256; s=rsa; d=manchego.org;",
+                    "ARC-Message-Signature: i=1; a=rsa-sha256; d=manchego.org; s=rsa;",
+                    1,
+                ),
+                DkimResult::Fail(crate::Error::FailedVerification),
+            ),
+            (
+                "Modified prior ARC-Authentication-Results",
+                raw_message.replacen(
+                    "ARC-Authentication-Results: i=1; manchego.org",
+                    "ARC-Authentication-Results: i=1; manchego.org; dkim=pass",
+                    1,
+                ),
+                DkimResult::Fail(crate::Error::FailedVerification),
+            ),
+        ] {
+            let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+            assert_eq!(
+                resolver.verify_arc(&message).await.result(),
+                &expected,
+                "{test}"
+            );
+        }
+    }
+
     async fn arc_verify_and_seal(
         resolver: &Resolver,
         raw_message: &str,
@@ -12,7 +12,7 @@ use std::time::SystemTime;
 
 use crate::{
     common::{
-        crypto::HashAlgorithm,
+        crypto::{digest_eq, HashAlgorithm},
         headers::Header,
         verify::{DomainKey, VerifySignature},
     },
@@ -90,8 +90,9 @@ impl Resolver {
                             })
                             .unwrap()
                             .3;
-                        if bh != &signature.bh {
-                            output.result = DkimResult::Neutral(Error::FailedBodyHashMatch);
+                        if !digest_eq(bh, &signature.bh) {
+                            output.result =
+                                DkimResult::Fail(Error::ArcFailedBodyHashMatch(signature.i));
                         }
                     } else {
                         output.result = DkimResult::Neutral(Error::SignatureExpired);
@@ -117,7 +118,8 @@ impl Resolver {
 
         // Hash headers
         let dkim_hdr_value = header.value.strip_signature();
-        let mut headers = message.signed_headers(&signature.h, header.name, &dkim_hdr_value);
+        let headers = message.signed_headers_normalized(&signature.h, header.name, &dkim_hdr_value);
+        let mut headers = headers.iter().map(|(name, value)| (*name, value.as_ref()));
 
         // Obtain record
         let record = match self.txt_lookup::<DomainKey>(signature.domain_key()).await {
@@ -144,7 +146,9 @@ impl Resolver {
                 }
             };
 
-            // Build Seal headers
+            // Build Seal headers: the AAR, AMS and AS of every set up to this
+            // one in instance order, with the b= tag of this seal emptied and
+            // without the body (RFC 8617 section 5.1.1)
             let seal_signature = header.value.strip_signature();
             let mut headers = output
                 .set
@@ -185,7 +189,7 @@ mod test {
 
     use crate::{
         common::{parse::TxtRecordParser, verify::DomainKey},
-        AuthenticatedMessage, DkimResult, Resolver,
+        AuthenticatedMessage, DkimResult, Error, Resolver,
     };
 
     #[tokio::test]
@@ -210,8 +214,33 @@ mod test {
             let arc = resolver.verify_arc(&message).await;
             assert_eq!(arc.result(), &DkimResult::Pass);
 
+            if file_name.ends_with("002.txt") {
+                assert_eq!(
+                    arc.authentication_results(1).unwrap(),
+                    "scamorza.org; dkim=pass header.d=manchego.org header.s=rsa header.b=IN4oMvqq"
+                );
+                assert_eq!(
+                    arc.latest_authentication_results().unwrap(),
+                    "manchego.org; dkim=pass header.d=manchego.org header.s=rsa header.b=IN4oMvqq"
+                );
+                assert_eq!(arc.authentication_results(3), None);
+                assert_eq!(arc.sealer_domains(), ["scamorza.org", "manchego.org"]);
+            }
+
             let dkim = resolver.verify_dkim(&message).await;
             assert!(dkim.iter().any(|o| o.result() == &DkimResult::Pass));
+
+            // A modified body breaks the latest ARC-Message-Signature
+            let tampered_message = raw_message.replacen("\r\n\r\n", "\r\n\r\nTampered.\r\n", 1);
+            let tampered = AuthenticatedMessage::parse(tampered_message.as_bytes()).unwrap();
+            let arc = resolver.verify_arc(&tampered).await;
+            assert_eq!(
+                arc.result(),
+                &DkimResult::Fail(Error::ArcFailedBodyHashMatch(
+                    message.ams_headers.len() as u32
+                ))
+            );
+            assert!(arc.sealer_domains().is_empty());
         }
     }
 
@@ -14,8 +14,6 @@ use std::{
     net::IpAddr,
 };
 
-use mail_builder::encoders::base64::base64_encode;
-
 use crate::{
     ArcOutput, AuthenticationResults, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error,
     IprevOutput, IprevResult, ReceivedSpf, SpfOutput, SpfResult,
@@ -50,23 +48,8 @@ impl<'x> AuthenticationResults<'x> {
             self.auth_results.push_str(";\r\n\tdkim-atps=");
         }
         dkim.result.as_auth_result(&mut self.auth_results);
-        if let Some(signature) = &dkim.signature {
-            if !signature.i.is_empty() {
-                self.auth_results.push_str(" header.i=");
-                self.auth_results.push_str(&signature.i);
-            } else {
-                self.auth_results.push_str(" header.d=");
-                self.auth_results.push_str(&signature.d);
-            }
-            self.auth_results.push_str(" header.s=");
-            self.auth_results.push_str(&signature.s);
-            if signature.b.len() >= 6 {
-                self.auth_results.push_str(" header.b=");
-                self.auth_results.push_str(
-                    &String::from_utf8(base64_encode(&signature.b[..6]).unwrap_or_default())
-                        .unwrap_or_default(),
-                );
-            }
+        for (property, value) in dkim.properties() {
+            write!(self.auth_results, " {property}={value}").ok();
         }
 
         if dkim.is_atps {
@@ -207,34 +190,35 @@ impl ReceivedSpf {
 
 impl SpfResult {
     fn as_spf_result(&self, header: &mut String, hostname: &str, mail_from: &str, ip_addr: IpAddr) {
+        header.push_str(self.as_str());
         match &self {
             SpfResult::Pass => write!(
                 header,
-                "pass ({hostname}: domain of {mail_from} designates {ip_addr} as permitted sender)",
+                " ({hostname}: domain of {mail_from} designates {ip_addr} as permitted sender)",
             ),
             SpfResult::Fail => write!(
                 header,
-                "fail ({hostname}: domain of {mail_from} does not designate {ip_addr} as permitted sender)",
+                " ({hostname}: domain of {mail_from} does not designate {ip_addr} as permitted sender)",
             ),
             SpfResult::SoftFail => write!(
                 header,
-                "softfail ({hostname}: domain of {mail_from} reports soft fail for {ip_addr})",
+                " ({hostname}: domain of {mail_from} reports soft fail for {ip_addr})",
             ),
             SpfResult::Neutral => write!(
                 header,
-                "neutral ({hostname}: domain of {mail_from} reports neutral for {ip_addr})",
+                " ({hostname}: domain of {mail_from} reports neutral for {ip_addr})",
             ),
             SpfResult::TempError => write!(
                 header,
-                "temperror ({hostname}: temporary dns error validating {mail_from})",
+                " ({hostname}: temporary dns error validating {mail_from})",
             ),
             SpfResult::PermError => write!(
                 header,
-                "permerror ({hostname}: unable to verify SPF record for {mail_from})",
+                " ({hostname}: unable to verify SPF record for {mail_from})",
             ),
             SpfResult::None => write!(
                 header,
-                "none ({hostname}: no SPF records found for {mail_from})",
+                " ({hostname}: no SPF records found for {mail_from})",
             ),
         }
         .ok();
@@ -247,67 +231,37 @@ pub trait AsAuthResult {
 
 impl AsAuthResult for DmarcResult {
     fn as_auth_result(&self, header: &mut String) {
+        header.push_str(self.as_str());
         match &self {
-            DmarcResult::Pass => header.push_str("pass"),
-            DmarcResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
-            }
-            DmarcResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
-            }
-            DmarcResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
+            DmarcResult::Fail(err) | DmarcResult::PermError(err) | DmarcResult::TempError(err) => {
+                err.as_auth_result(header)
             }
-            DmarcResult::None => header.push_str("none"),
+            DmarcResult::Pass | DmarcResult::None => (),
         }
     }
 }
 
 impl AsAuthResult for IprevResult {
     fn as_auth_result(&self, header: &mut String) {
+        header.push_str(self.as_str());
         match &self {
-            IprevResult::Pass => header.push_str("pass"),
-            IprevResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
+            IprevResult::Fail(err) | IprevResult::PermError(err) | IprevResult::TempError(err) => {
+                err.as_auth_result(header)
             }
-            IprevResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
-            }
-            IprevResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
-            }
-            IprevResult::None => header.push_str("none"),
+            IprevResult::Pass | IprevResult::None => (),
         }
     }
 }
 
 impl AsAuthResult for DkimResult {
     fn as_auth_result(&self, header: &mut String) {
+        header.push_str(self.as_str());
         match &self {
-            DkimResult::Pass => header.push_str("pass"),
-            DkimResult::Neutral(err) => {
-                header.push_str("neutral");
-                err.as_auth_result(header);
-            }
-            DkimResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
-            }
-            DkimResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
-            }
-            DkimResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
-            }
-            DkimResult::None => header.push_str("none"),
+            DkimResult::Neutral(err)
+            | DkimResult::Fail(err)
+            | DkimResult::PermError(err)
+            | DkimResult::TempError(err) => err.as_auth_result(header),
+            DkimResult::Pass | DkimResult::None => (),
         }
     }
 }
@@ -317,6 +271,8 @@ impl AsAuthResult for Error {
         header.push_str(" (");
         header.push_str(match self {
             Error::ParseError => "dns record parse error",
+            Error::InvalidCharacter => "dns record contains control characters",
+            Error::InvalidMacro => "invalid macro",
             Error::MissingParameters => "missing parameters",
             Error::NoHeadersFound => "no headers found",
             Error::CryptoError(_) => "verification failed",
@@ -325,24 +281,31 @@ impl AsAuthResult for Error {
             Error::UnsupportedVersion => "unsupported version",
             Error::UnsupportedAlgorithm => "unsupported algorithm",
             Error::UnsupportedCanonicalization => "unsupported canonicalization",
+            Error::UnsupportedQueryMethod => "unsupported query method",
             Error::UnsupportedKeyType => "unsupported key type",
             Error::FailedBodyHashMatch => "body hash did not verify",
             Error::FailedVerification => "verification failed",
             Error::FailedAuidMatch => "auid does not match",
+            Error::UnsignedHeaderInstance => "unsigned header instance",
             Error::RevokedPublicKey => "revoked public key",
             Error::IncompatibleAlgorithms => "incompatible record/signature algorithms",
-            Error::SignatureExpired => "signature error",
+            Error::SignatureExpired | Error::SignatureInFuture => "signature error",
             Error::DnsError(_) => "dns error",
             Error::DnsRecordNotFound(_) => "dns record not found",
             Error::ArcInvalidInstance(i) => {
                 write!(header, "invalid ARC instance {i})").ok();
                 return;
             }
+            Error::ArcFailedBodyHashMatch(i) => {
+                write!(header, "ARC instance {i} body hash did not verify)").ok();
+                return;
+            }
             Error::ArcInvalidCV => "invalid ARC cv",
             Error::ArcChainTooLong => "too many ARC headers",
             Error::ArcHasHeaderTag => "ARC has header tag",
             Error::ArcBrokenChain => "broken ARC chain",
             Error::NotAligned => "policy not aligned",
+            Error::MultipleFromDomains => "multiple from domains",
             Error::InvalidRecordType => "invalid dns record type",
         });
         header.push(')');
@@ -352,18 +315,20 @@ impl AsAuthResult for Error {
 #[cfg(test)]
 mod test {
     use crate::{
-        dkim::Signature, dmarc::Policy, ArcOutput, AuthenticationResults, DkimOutput, DkimResult,
-        DmarcOutput, DmarcResult, Error, IprevOutput, IprevResult, ReceivedSpf, SpfOutput,
-        SpfResult,
+        common::crypto::Algorithm, dkim::Signature, dmarc::Policy, ArcOutput,
+        AuthenticationResults, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error,
+        IprevOutput, IprevResult, ReceivedSpf, SpfOutput, SpfResult,
     };
 
+    use super::AsAuthResult;
+
     #[test]
     fn authentication_results() {
         let mut auth_results = AuthenticationResults::new("mydomain.org");
 
         for (expected_auth_results, dkim) in [
             (
-                "dkim=pass header.d=example.org header.s=myselector",
+                "dkim=pass header.d=example.org header.s=myselector header.a=rsa-sha256",
                 DkimOutput {
                     result: DkimResult::Pass,
                     signature: (&Signature {
@@ -379,13 +344,16 @@ mod test {
             (
                 concat!(
                     "dkim=fail (verification failed) header.d=example.org ",
-                    "header.s=myselector header.b=MTIzNDU2"
+                    "header.i=@mail.example.org header.s=myselector ",
+                    "header.a=ed25519-sha256 header.b=MTIzNDU2"
                 ),
                 DkimOutput {
                     result: DkimResult::Fail(Error::FailedVerification),
                     signature: (&Signature {
                         d: "example.org".into(),
+                        i: "@mail.example.org".into(),
                         s: "myselector".into(),
+                        a: Algorithm::Ed25519Sha256,
                         b: b"123456".to_vec(),
                         ..Default::default()
                     })
@@ -397,7 +365,8 @@ mod test {
             (
                 concat!(
                     "dkim-atps=temperror (dns error) header.d=atps.example.org ",
-                    "header.s=otherselctor header.b=YWJjZGVm header.from=jdoe@example.org"
+                    "header.s=otherselctor header.a=rsa-sha256 header.b=YWJjZGVm ",
+                    "header.from=jdoe@example.org"
                 ),
                 DkimOutput {
                     result: DkimResult::TempError(Error::DnsError("".to_string())),
@@ -486,6 +455,11 @@ mod test {
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
+                    matched: None,
+                    ip: None,
+                    valid_until: None,
+                    policy_hint: None,
+                    trusted_forwarder: false,
                 },
                 ip_addr,
                 mail_from,
@@ -497,6 +471,11 @@ mod test {
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
+                    matched: None,
+                    ip: None,
+                    valid_until: None,
+                    policy_hint: None,
+                    trusted_forwarder: false,
                 },
                 ip_addr,
                 helo,
@@ -589,4 +568,62 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn result_tokens() {
+        for (result, expected) in [
+            (SpfResult::Pass, "pass"),
+            (SpfResult::Fail, "fail"),
+            (SpfResult::SoftFail, "softfail"),
+            (SpfResult::Neutral, "neutral"),
+            (SpfResult::TempError, "temperror"),
+            (SpfResult::PermError, "permerror"),
+            (SpfResult::None, "none"),
+        ] {
+            assert_eq!(result.to_string(), expected);
+        }
+
+        for (result, expected) in [
+            (DkimResult::Pass, "pass"),
+            (DkimResult::Fail(Error::FailedVerification), "fail"),
+            (DkimResult::Neutral(Error::FailedBodyHashMatch), "neutral"),
+            (
+                DkimResult::TempError(Error::DnsError("".to_string())),
+                "temperror",
+            ),
+            (DkimResult::PermError(Error::ParseError), "permerror"),
+            (DkimResult::None, "none"),
+        ] {
+            assert_eq!(result.to_string(), expected);
+            let mut header = String::new();
+            result.as_auth_result(&mut header);
+            assert!(header.starts_with(expected), "{header}");
+        }
+
+        for (result, expected) in [
+            (DmarcResult::Pass, "pass"),
+            (DmarcResult::Fail(Error::NotAligned), "fail"),
+            (
+                DmarcResult::TempError(Error::DnsError("".to_string())),
+                "temperror",
+            ),
+            (DmarcResult::PermError(Error::ParseError), "permerror"),
+            (DmarcResult::None, "none"),
+        ] {
+            assert_eq!(result.to_string(), expected);
+        }
+
+        for (result, expected) in [
+            (IprevResult::Pass, "pass"),
+            (IprevResult::Fail(Error::NotAligned), "fail"),
+            (
+                IprevResult::TempError(Error::DnsError("".to_string())),
+                "temperror",
+            ),
+            (IprevResult::PermError(Error::ParseError), "permerror"),
+            (IprevResult::None, "none"),
+        ] {
+            assert_eq!(result.to_string(), expected);
+        }
+    }
 }
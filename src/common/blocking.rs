@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Blocking wrappers around the async verification API, for applications
+//! without an async runtime.
+//!
+//! The wrappers drive the async API on a small internal Tokio runtime, which
+//! also runs the connection tasks of any [`Resolver`] used through them. They
+//! panic when called from within a Tokio runtime, as blocking a runtime
+//! worker thread is not allowed; async code should call the async methods.
+
+use std::{future::Future, net::IpAddr, sync::OnceLock};
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    ArcOutput, AuthResults, AuthenticatedMessage, DkimOutput, DmarcOutput, IprevOutput, Resolver,
+    SpfOutput,
+};
+
+impl Resolver {
+    /// Verifies the SPF EHLO identity, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_spf_helo_blocking(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+    ) -> SpfOutput {
+        block_on(self.verify_spf_helo(ip, helo_domain, host_domain))
+    }
+
+    /// Verifies the SPF MAIL FROM identity, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_spf_sender_blocking(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+    ) -> SpfOutput {
+        block_on(self.verify_spf_sender(ip, helo_domain, host_domain, sender))
+    }
+
+    /// Verifies both the SPF EHLO and MAIL FROM identities, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_spf_blocking(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+    ) -> SpfOutput {
+        block_on(self.verify_spf(ip, helo_domain, host_domain, mail_from))
+    }
+
+    /// Verifies DKIM headers of an RFC5322 message, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_dkim_blocking<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+    ) -> Vec<DkimOutput<'x>> {
+        block_on(self.verify_dkim(message))
+    }
+
+    /// Verifies ARC headers of an RFC5322 message, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_arc_blocking<'x>(&self, message: &'x AuthenticatedMessage<'x>) -> ArcOutput<'x> {
+        block_on(self.verify_arc(message))
+    }
+
+    /// Verifies the DMARC policy of an RFC5322.From domain, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_dmarc_blocking(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        dkim_output: &[DkimOutput<'_>],
+        mail_from_domain: &str,
+        spf_output: &SpfOutput,
+    ) -> DmarcOutput {
+        block_on(self.verify_dmarc(message, dkim_output, mail_from_domain, spf_output))
+    }
+
+    /// Verifies the reverse DNS of an IP address, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn verify_iprev_blocking(&self, addr: IpAddr) -> IprevOutput {
+        block_on(self.verify_iprev(addr))
+    }
+
+    /// Verifies the SPF, DKIM and DMARC results of a message, blocking the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from within a Tokio runtime.
+    pub fn authenticate_blocking<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+    ) -> AuthResults<'x> {
+        block_on(self.authenticate(message, ip, helo_domain, host_domain, mail_from))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+    RUNTIME
+        .get_or_init(|| {
+            Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("mail-auth-resolver")
+                .enable_all()
+                .build()
+                .expect("Failed to build blocking runtime")
+        })
+        .block_on(future)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{common::parse::TxtRecordParser, spf::Spf, Resolver, SpfResult};
+
+    #[test]
+    fn verify_spf_blocking() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 -all").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        for (ip, result) in [
+            ("192.0.2.1", SpfResult::Pass),
+            ("198.51.100.1", SpfResult::Fail),
+        ] {
+            assert_eq!(
+                resolver
+                    .verify_spf_sender_blocking(
+                        ip.parse().unwrap(),
+                        "mx.example.org",
+                        "localhost",
+                        "sender@example.org",
+                    )
+                    .result(),
+                result
+            );
+        }
+    }
+}
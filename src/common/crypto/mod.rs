@@ -1,5 +1,6 @@
 #[cfg(feature = "sha1")]
 use sha1::{digest::Output, Digest};
+use subtle::ConstantTimeEq;
 
 use crate::{dkim::Canonicalization, Result};
 
@@ -83,6 +84,7 @@ pub struct Sha1;
 pub struct Sha256;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u64)]
 pub enum HashAlgorithm {
     Sha1 = R_HASH_SHA1,
@@ -144,7 +146,14 @@ impl AsRef<[u8]> for HashOutput {
     }
 }
 
+/// Compares two digests in constant time, so that a mismatch does not reveal
+/// how many leading bytes of the expected value were guessed correctly.
+pub(crate) fn digest_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Algorithm {
     RsaSha1,
     #[default]
@@ -152,5 +161,37 @@ pub enum Algorithm {
     Ed25519Sha256,
 }
 
+impl Algorithm {
+    /// Returns the algorithm name used in the `a=` tag.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::RsaSha256 => "rsa-sha256",
+            Algorithm::RsaSha1 => "rsa-sha1",
+            Algorithm::Ed25519Sha256 => "ed25519-sha256",
+        }
+    }
+}
+
 pub(crate) const R_HASH_SHA1: u64 = 0x01;
 pub(crate) const R_HASH_SHA256: u64 = 0x02;
+
+#[cfg(test)]
+mod test {
+    use super::digest_eq;
+
+    #[test]
+    fn digest_eq_matches() {
+        let digest = [0x5au8; 32];
+        assert!(digest_eq(&digest, &digest.clone()));
+        assert!(digest_eq(&[], &[]));
+
+        let mut first = digest;
+        first[0] ^= 1;
+        let mut last = digest;
+        last[31] ^= 0x80;
+        for other in [&first[..], &last[..], &digest[..31], &[][..]] {
+            assert!(!digest_eq(&digest, other));
+            assert!(!digest_eq(other, &digest));
+        }
+    }
+}
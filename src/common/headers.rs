@@ -132,14 +132,17 @@ impl<'x> Iterator for HeaderIterator<'x> {
                         colon_pos = pos;
                     }
                     b'\n' => {
-                        if last_ch == b'\r' || self.start_pos == pos {
+                        let is_folded = pos > self.start_pos + usize::from(last_ch == b'\r')
+                            && self
+                                .iter
+                                .peek()
+                                .is_some_and(|(_, next_byte)| [b' ', b'\t'].contains(next_byte));
+                        if is_folded {
+                            // Obsolete folding between the name and the colon
+                        } else if last_ch == b'\r' || self.start_pos == pos {
                             // End of headers
                             return None;
-                        } else if self
-                            .iter
-                            .peek()
-                            .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
-                        {
+                        } else {
                             // Invalid header, return anyway.
                             let header_name = self
                                 .message
@@ -219,14 +222,17 @@ impl<'x> Iterator for HeaderParser<'x> {
                         colon_pos = pos;
                     }
                     b'\n' => {
-                        if last_ch == b'\r' || self.start_pos == pos {
+                        let is_folded = pos > self.start_pos + usize::from(last_ch == b'\r')
+                            && self
+                                .iter
+                                .peek()
+                                .is_some_and(|(_, next_byte)| [b' ', b'\t'].contains(next_byte));
+                        if is_folded {
+                            // Obsolete folding between the name and the colon
+                        } else if last_ch == b'\r' || self.start_pos == pos {
                             // End of headers
                             return None;
-                        } else if self
-                            .iter
-                            .peek()
-                            .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
-                        {
+                        } else {
                             // Invalid header, return anyway.
                             let header_name = self
                                 .message
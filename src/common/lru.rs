@@ -27,6 +27,10 @@ pub trait DnsCache<K, V>: Sized {
         K: Borrow<Q>,
         Q: Hash + Eq;
     fn insert(&self, name: K, value: V, valid_until: Instant) -> V;
+    fn valid_until<Q>(&self, name: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
 }
 
 impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
@@ -62,4 +66,15 @@ impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
         );
         item
     }
+
+    fn valid_until<Q>(&self, name: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock()
+            .get_mut(name)
+            .map(|entry| entry.valid_until)
+            .filter(|valid_until| *valid_until >= Instant::now())
+    }
 }
@@ -8,14 +8,36 @@
  * except according to those terms.
  */
 
+use std::borrow::Cow;
+
 use mail_parser::{parsers::MessageStream, Address, HeaderValue};
 
-use crate::{arc, common::crypto::HashAlgorithm, dkim, AuthenticatedMessage};
+use crate::{
+    arc,
+    common::crypto::HashAlgorithm,
+    dkim::{self, canonicalize::BodyHasher},
+    AuthenticatedMessage,
+};
 
 use super::headers::{AuthenticatedHeader, Header, HeaderParser};
 
+// Below this size spawning threads costs more than hashing the body serially.
+#[cfg(feature = "parallel-hash")]
+const PARALLEL_HASH_MIN_LEN: usize = 256 * 1024;
+
 impl<'x> AuthenticatedMessage<'x> {
     pub fn parse(raw_message: &'x [u8]) -> Option<Self> {
+        Self::parse_with_opts(raw_message, false)
+    }
+
+    /// Parses a message, optionally treating bare LF line endings as CRLF.
+    ///
+    /// When `normalize_line_endings` is enabled, bare LFs in the body and in
+    /// the signed header values are converted to CRLF before hashing. This
+    /// changes the computed hashes, so only enable it for messages that were
+    /// signed with CRLF line endings and converted to LF in transit (for
+    /// example by a local delivery agent).
+    pub fn parse_with_opts(raw_message: &'x [u8], normalize_line_endings: bool) -> Option<Self> {
         let mut message = AuthenticatedMessage {
             headers: Vec::new(),
             from: Vec::new(),
@@ -29,6 +51,7 @@ impl<'x> AuthenticatedMessage<'x> {
             received_headers_count: 0,
             date_header_present: false,
             message_id_header_present: false,
+            normalize_line_endings,
         };
 
         let mut headers = HeaderParser::new(raw_message);
@@ -139,10 +162,41 @@ impl<'x> AuthenticatedMessage<'x> {
             message.body_offset = raw_message.len();
         }
         let body = raw_message.get(message.body_offset..).unwrap_or_default();
+        let body = if normalize_line_endings {
+            to_crlf(body)
+        } else {
+            Cow::Borrowed(body)
+        };
+
+        // Calculate body hashes, splitting them across at most one thread per
+        // core for large bodies
+        #[cfg(feature = "parallel-hash")]
+        let workers = if message.body_hashes.len() > 1 && body.len() >= PARALLEL_HASH_MIN_LEN {
+            std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(message.body_hashes.len())
+        } else {
+            1
+        };
+        #[cfg(not(feature = "parallel-hash"))]
+        let workers = 1;
 
-        // Calculate body hashes
-        for (cb, ha, l, bh) in &mut message.body_hashes {
-            *bh = ha.hash(cb.canonical_body(body, *l)).as_ref().to_vec();
+        if workers > 1 {
+            let body = body.as_ref();
+            let chunk_len = message.body_hashes.len().div_ceil(workers);
+            std::thread::scope(|scope| {
+                for chunk in message.body_hashes.chunks_mut(chunk_len) {
+                    scope.spawn(move || {
+                        for (cb, ha, l, bh) in chunk {
+                            *bh = BodyHasher::new(*cb, *ha, *l).hash(body);
+                        }
+                    });
+                }
+            });
+        } else {
+            for (cb, ha, l, bh) in &mut message.body_hashes {
+                *bh = BodyHasher::new(*cb, *ha, *l).hash(&body);
+            }
         }
 
         // Sort ARC headers
@@ -205,6 +259,76 @@ impl<'x> AuthenticatedMessage<'x> {
         self.body_offset
     }
 
+    pub fn body_hashes(&self) -> &[(dkim::Canonicalization, HashAlgorithm, u64, Vec<u8>)] {
+        &self.body_hashes
+    }
+
+    /// Returns the computed body hash matching the canonicalization, hash algorithm
+    /// and body length of a DKIM signature.
+    pub fn body_hash(&self, signature: &dkim::Signature) -> Option<&[u8]> {
+        let ha = HashAlgorithm::from(signature.a);
+        self.body_hashes
+            .iter()
+            .find(|(c, h, l, _)| c == &signature.cb && h == &ha && l == &signature.l)
+            .map(|(_, _, _, bh)| bh.as_slice())
+    }
+
+    /// Returns the RFC5322.From domain used for DMARC evaluation. If the From
+    /// header lists addresses in more than one domain, the first domain is
+    /// returned as an error.
+    pub fn from_domain(&self) -> Result<Option<&str>, &str> {
+        let mut domains = self.from_domains();
+        match (domains.next(), domains.next()) {
+            (Some(from_domain), Some(_)) => Err(from_domain),
+            (from_domain, _) => Ok(from_domain),
+        }
+    }
+
+    /// Returns the distinct domains of the RFC5322.From addresses, compared
+    /// ignoring case, in the order they first appear.
+    pub fn from_domains(&self) -> impl Iterator<Item = &str> {
+        let mut seen: Vec<&str> = Vec::new();
+        self.from
+            .iter()
+            .filter_map(|from| from.rsplit_once('@').map(|(_, domain)| domain))
+            .filter(|domain| !domain.is_empty())
+            .filter(move |domain| {
+                if seen.iter().any(|d| d.eq_ignore_ascii_case(domain)) {
+                    false
+                } else {
+                    seen.push(domain);
+                    true
+                }
+            })
+    }
+
+    /// Returns the raw message without the DKIM-Signature headers for which
+    /// `strip` returns `true`. All other bytes are copied unchanged.
+    pub fn strip_dkim_signatures(
+        &self,
+        strip: impl Fn(&crate::Result<dkim::Signature>) -> bool,
+    ) -> Vec<u8> {
+        let base = self.raw_message.as_ptr() as usize;
+        let mut stripped = Vec::with_capacity(self.raw_message.len());
+        let mut pos = 0;
+
+        for header in &self.dkim_headers {
+            if strip(&header.header) {
+                let start = header.name.as_ptr() as usize - base;
+                let end = if !header.value.is_empty() {
+                    header.value.as_ptr() as usize - base + header.value.len()
+                } else {
+                    start + header.name.len()
+                };
+                stripped.extend_from_slice(self.raw_message.get(pos..start).unwrap_or_default());
+                pos = end;
+            }
+        }
+        stripped.extend_from_slice(self.raw_message.get(pos..).unwrap_or_default());
+
+        stripped
+    }
+
     pub fn froms(&self) -> &[String] {
         &self.from
     }
@@ -213,3 +337,128 @@ impl<'x> AuthenticatedMessage<'x> {
         self.from.first().map_or("", |f| f.as_str())
     }
 }
+
+/// Converts bare LF line endings to CRLF, borrowing the input when
+/// no conversion is needed.
+pub(crate) fn to_crlf(data: &[u8]) -> Cow<'_, [u8]> {
+    let mut bare_lfs = 0;
+    let mut last_ch = 0;
+    for &ch in data {
+        if ch == b'\n' && last_ch != b'\r' {
+            bare_lfs += 1;
+        }
+        last_ch = ch;
+    }
+    if bare_lfs == 0 {
+        return Cow::Borrowed(data);
+    }
+
+    let mut result = Vec::with_capacity(data.len() + bare_lfs);
+    let mut last_ch = 0;
+    for &ch in data {
+        if ch == b'\n' && last_ch != b'\r' {
+            result.push(b'\r');
+        }
+        result.push(ch);
+        last_ch = ch;
+    }
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::crypto::HashAlgorithm,
+        dkim::{canonicalize::BodyHasher, Canonicalization},
+        AuthenticatedMessage,
+    };
+
+    #[test]
+    fn body_hash_footer() {
+        let headers = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/simple; d=example.org; ",
+            "s=default; h=From; bh=AAAA; b=AAAA\r\n",
+            "From: hello@example.org\r\n\r\n",
+        );
+        let body = "Hi!\r\n";
+        let footer = "--\r\nSent via the example.org mailing list\r\n";
+
+        let message_ = format!("{headers}{body}");
+        let message = AuthenticatedMessage::parse(message_.as_bytes()).unwrap();
+        let signature = message.dkim_headers[0].header.as_ref().unwrap();
+        let bh = message.body_hash(signature).unwrap().to_vec();
+        assert_eq!(message.body_hashes().len(), 1);
+
+        let footer_message_ = format!("{headers}{body}{footer}");
+        let footer_message = AuthenticatedMessage::parse(footer_message_.as_bytes()).unwrap();
+        let signature = footer_message.dkim_headers[0].header.as_ref().unwrap();
+        assert_ne!(footer_message.body_hash(signature).unwrap(), bh);
+    }
+
+    #[test]
+    fn body_hash_reuse() {
+        let message_ = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.org; ",
+            "s=default; h=From; bh=AAAA; b=AAAA\r\n",
+            "DKIM-Signature: v=1; a=ed25519-sha256; c=simple/relaxed; d=example.com; ",
+            "s=default; h=From; bh=AAAA; b=AAAA\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/simple; d=example.net; ",
+            "s=default; h=From; bh=AAAA; b=AAAA\r\n",
+            "From: hello@example.org\r\n\r\n",
+            "Hi  there!\r\n\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message_.as_bytes()).unwrap();
+        assert_eq!(message.body_hashes().len(), 2);
+
+        let signatures = message
+            .dkim_headers
+            .iter()
+            .map(|h| h.header.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        let bh_org = message.body_hash(signatures[0]).unwrap();
+        let bh_com = message.body_hash(signatures[1]).unwrap();
+        let bh_net = message.body_hash(signatures[2]).unwrap();
+        assert!(std::ptr::eq(bh_org, bh_com));
+        assert_ne!(bh_org, bh_net);
+
+        let hasher = BodyHasher::for_signature(signatures[0]);
+        assert_eq!(hasher, BodyHasher::for_signature(signatures[1]));
+        assert_eq!(hasher.hash(message.raw_body()), bh_org);
+        assert_eq!(
+            BodyHasher::new(Canonicalization::Simple, HashAlgorithm::Sha256, 0)
+                .hash(message.raw_body()),
+            bh_net
+        );
+
+        assert!(!signatures[0].body_hash_matches(bh_org));
+        let mut signature = signatures[0].clone();
+        signature.bh = bh_org.to_vec();
+        assert!(signature.body_hash_matches(bh_org));
+        assert!(!signature.body_hash_matches(bh_net));
+    }
+
+    #[test]
+    fn body_hash_parallel() {
+        let mut message_ = String::new();
+        for (a, c, d) in [
+            ("rsa-sha256", "relaxed/relaxed", "example.org"),
+            ("rsa-sha256", "relaxed/simple", "example.com"),
+            ("rsa-sha1", "relaxed/relaxed", "example.net"),
+            ("rsa-sha1", "simple/simple", "example.edu"),
+        ] {
+            message_.push_str(&format!(
+                "DKIM-Signature: v=1; a={a}; c={c}; d={d}; s=default; h=From; bh=AAAA; b=AAAA\r\n"
+            ));
+        }
+        message_.push_str("From: hello@example.org\r\n\r\n");
+        while message_.len() < 4 * 1024 * 1024 {
+            message_.push_str("The quick  brown fox jumps over the lazy dog. \t \r\n");
+        }
+
+        let message = AuthenticatedMessage::parse(message_.as_bytes()).unwrap();
+        assert_eq!(message.body_hashes().len(), 4);
+        for (cb, ha, l, bh) in message.body_hashes() {
+            assert_eq!(&BodyHasher::new(*cb, *ha, *l).hash(message.raw_body()), bh);
+        }
+    }
+}
@@ -12,6 +12,8 @@ use crate::{Error, IprevResult};
 
 pub mod auth_results;
 pub mod base32;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod crypto;
 pub mod headers;
 pub mod lru;
@@ -25,6 +25,7 @@ pub(crate) const L: u64 = b'l' as u64;
 pub(crate) const N: u64 = b'n' as u64;
 pub(crate) const O: u64 = b'o' as u64;
 pub(crate) const P: u64 = b'p' as u64;
+pub(crate) const Q: u64 = b'q' as u64;
 pub(crate) const R: u64 = b'r' as u64;
 pub(crate) const S: u64 = b's' as u64;
 pub(crate) const T: u64 = b't' as u64;
@@ -35,6 +36,12 @@ pub(crate) const Z: u64 = b'z' as u64;
 
 pub trait TxtRecordParser: Sized {
     fn parse(record: &[u8]) -> crate::Result<Self>;
+
+    /// Returns `false` if publishing more than one record of this type
+    /// at the same name is an error.
+    fn allows_multiple_records() -> bool {
+        true
+    }
 }
 
 pub(crate) trait TagParser: Sized {
@@ -407,3 +414,48 @@ impl ItemParser for Cow<'_, str> {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::verify::DomainKey,
+        dkim::{Atps, DomainKeyReport, Signature},
+        dmarc::{Dmarc, Policy},
+        mta_sts::{MtaSts, TlsRpt},
+        spf::Spf,
+        Error,
+    };
+
+    use super::TxtRecordParser;
+
+    #[test]
+    fn record_from_str() {
+        let record = "v=spf1 mx -all";
+        let spf: Spf = record.parse().unwrap();
+        assert_eq!(spf, Spf::parse(record.as_bytes()).unwrap());
+        assert_eq!("v=spf1 mx:".parse::<Spf>(), Err(Error::ParseError));
+        assert_eq!("v=DKIM1".parse::<Spf>(), Err(Error::InvalidRecordType));
+
+        let dmarc: Dmarc = "v=DMARC1; p=reject".parse().unwrap();
+        assert_eq!(dmarc.p, Policy::Reject);
+        assert_eq!("v=spf1".parse::<Dmarc>(), Err(Error::InvalidRecordType));
+
+        let signature: Signature = concat!(
+            "v=1; a=rsa-sha256; d=example.org; s=default; ",
+            "h=From; bh=AAAA; b=AAAA"
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(signature.d, "example.org");
+
+        assert!("v=DKIM1; p=".parse::<DomainKey>().is_ok());
+        assert!("ra=dkim-failures; rp=100"
+            .parse::<DomainKeyReport>()
+            .is_ok());
+        assert!("v=ATPS1;".parse::<Atps>().is_ok());
+        assert!("v=STSv1; id=20160831085700Z;".parse::<MtaSts>().is_ok());
+        assert!("v=TLSRPTv1; rua=mailto:tlsrpt@example.org"
+            .parse::<TlsRpt>()
+            .is_ok());
+    }
+}
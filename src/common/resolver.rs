@@ -10,14 +10,16 @@
 
 use std::{
     borrow::Cow,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
+    time::Duration,
 };
 
 use hickory_resolver::{
     config::{ResolverConfig, ResolverOpts},
     error::{ResolveError, ResolveErrorKind},
-    proto::rr::RecordType,
+    lookup::Lookup,
+    proto::rr::{RData, RecordType},
     system_conf::read_system_conf,
     AsyncResolver, Name,
 };
@@ -26,7 +28,7 @@ use crate::{
     dkim::{Atps, DomainKeyReport},
     dmarc::Dmarc,
     mta_sts::{MtaSts, TlsRpt},
-    spf::{Macro, Spf},
+    spf::{Macro, Mechanism, Spf, EVALUATION_TIMEOUT, MAX_EXPANSION_LEN},
     Error, IpLookupStrategy, Resolver, Txt, MX,
 };
 
@@ -37,6 +39,7 @@ use super::{
 };
 
 impl Resolver {
+    /// Creates a resolver using Cloudflare DNS over TLS.
     pub fn new_cloudflare_tls() -> Result<Self, ResolveError> {
         Self::with_capacity(
             ResolverConfig::cloudflare_tls(),
@@ -45,22 +48,33 @@ impl Resolver {
         )
     }
 
+    /// Creates a resolver using Cloudflare DNS over UDP/TCP.
     pub fn new_cloudflare() -> Result<Self, ResolveError> {
         Self::with_capacity(ResolverConfig::cloudflare(), ResolverOpts::default(), 128)
     }
 
+    /// Creates a resolver using Google DNS over TLS.
+    pub fn new_google_tls() -> Result<Self, ResolveError> {
+        Self::with_capacity(ResolverConfig::google_tls(), ResolverOpts::default(), 128)
+    }
+
+    /// Creates a resolver using Google DNS over UDP/TCP.
     pub fn new_google() -> Result<Self, ResolveError> {
         Self::with_capacity(ResolverConfig::google(), ResolverOpts::default(), 128)
     }
 
+    /// Creates a resolver using Quad9 DNS over UDP/TCP.
     pub fn new_quad9() -> Result<Self, ResolveError> {
         Self::with_capacity(ResolverConfig::quad9(), ResolverOpts::default(), 128)
     }
 
+    /// Creates a resolver using Quad9 DNS over TLS.
     pub fn new_quad9_tls() -> Result<Self, ResolveError> {
         Self::with_capacity(ResolverConfig::quad9_tls(), ResolverOpts::default(), 128)
     }
 
+    /// Creates a resolver using the system configuration
+    /// (`/etc/resolv.conf` on Unix, the registry on Windows).
     pub fn new_system_conf() -> Result<Self, ResolveError> {
         let (config, options) = read_system_conf()?;
         Self::with_capacity(config, options, 128)
@@ -78,6 +92,11 @@ impl Resolver {
             cache_ipv4: LruCache::with_capacity(capacity),
             cache_ipv6: LruCache::with_capacity(capacity),
             cache_ptr: LruCache::with_capacity(capacity),
+            spf_max_expansion: MAX_EXPANSION_LEN,
+            spf_timeout: EVALUATION_TIMEOUT,
+            spf_trusted_forwarders: Vec::new(),
+            #[cfg(any(test, feature = "test"))]
+            mock_dns: Default::default(),
         })
     }
 
@@ -97,9 +116,95 @@ impl Resolver {
             cache_ipv4: LruCache::with_capacity(ipv4_capacity),
             cache_ipv6: LruCache::with_capacity(ipv6_capacity),
             cache_ptr: LruCache::with_capacity(ptr_capacity),
+            spf_max_expansion: MAX_EXPANSION_LEN,
+            spf_timeout: EVALUATION_TIMEOUT,
+            spf_trusted_forwarders: Vec::new(),
+            #[cfg(any(test, feature = "test"))]
+            mock_dns: Default::default(),
         })
     }
 
+    /// Creates a resolver whose queries originate from the given local
+    /// address, for multi-homed hosts behind split-horizon DNS or source
+    /// based firewall rules. The port of each socket is chosen by the
+    /// operating system.
+    ///
+    /// Name servers of the other address family are dropped from the
+    /// configuration, as they can't be reached from the bound address. The
+    /// address has to be assigned to a local interface: binding fails at
+    /// query time otherwise, unless the platform allows non-local binds
+    /// (such as `net.ipv4.ip_nonlocal_bind` on Linux).
+    pub fn with_bind_addr(
+        config: ResolverConfig,
+        options: ResolverOpts,
+        bind_addr: IpAddr,
+    ) -> Result<Self, ResolveError> {
+        let mut bound_config =
+            ResolverConfig::from_parts(config.domain().cloned(), config.search().to_vec(), vec![]);
+        for name_server in config.name_servers() {
+            if name_server.socket_addr.is_ipv4() == bind_addr.is_ipv4() {
+                let mut name_server = name_server.clone();
+                name_server.bind_addr = SocketAddr::new(bind_addr, 0).into();
+                bound_config.add_name_server(name_server);
+            }
+        }
+        Self::with_capacity(bound_config, options, 128)
+    }
+
+    /// Sets the maximum length of a domain name produced by SPF macro
+    /// expansion. Records expanding to longer names evaluate to PermError.
+    pub fn with_spf_max_expansion(mut self, max_len: usize) -> Self {
+        self.spf_max_expansion = max_len;
+        self
+    }
+
+    /// Sets the time limit for evaluating an SPF record, including any nested
+    /// include and redirect records. It is checked before each DNS query and
+    /// evaluation returns TempError once it is exceeded. Defaults to 20 seconds.
+    pub fn with_spf_timeout(mut self, timeout: Duration) -> Self {
+        self.spf_timeout = timeout;
+        self
+    }
+
+    /// Adds a network of trusted forwarders, such as a mailing list or a
+    /// backup MX. SPF checks of clients within `addr/prefix_len` pass
+    /// without evaluating the SPF record of the domain, and the output
+    /// reports them as [`SpfOutput::is_trusted_forwarder`]. IPv4-mapped IPv6
+    /// networks are added as the equivalent IPv4 network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix_len` is longer than 32 bits for an IPv4 address or
+    /// 128 bits for an IPv6 address.
+    pub fn with_spf_trusted_forwarder(mut self, addr: IpAddr, prefix_len: u8) -> Self {
+        let (addr, prefix_len) = match addr {
+            IpAddr::V6(addr) if prefix_len <= 128 => match addr.to_ipv4_mapped() {
+                Some(addr) => (IpAddr::V4(addr), prefix_len.saturating_sub(96)),
+                None => (IpAddr::V6(addr), prefix_len),
+            },
+            _ => (addr, prefix_len),
+        };
+        self.spf_trusted_forwarders.push(match addr {
+            IpAddr::V4(addr) => {
+                assert!(prefix_len <= 32, "invalid IPv4 prefix length {prefix_len}");
+                Mechanism::Ip4 {
+                    addr,
+                    mask: u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0),
+                }
+            }
+            IpAddr::V6(addr) => {
+                assert!(prefix_len <= 128, "invalid IPv6 prefix length {prefix_len}");
+                Mechanism::Ip6 {
+                    addr,
+                    mask: u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0),
+                }
+            }
+        });
+        self
+    }
+
+    /// Returns the contents of all TXT records of a name concatenated into
+    /// a single buffer. The result is not cached.
     pub async fn txt_raw_lookup(&self, key: impl IntoFqdn<'_>) -> crate::Result<Vec<u8>> {
         let mut result = vec![];
         for record in self
@@ -119,6 +224,41 @@ impl Resolver {
         Ok(result)
     }
 
+    /// Returns the TXT records of a name, one string per record with its
+    /// character-strings joined. The result is not cached, as the cache only
+    /// holds parsed records; use [`Resolver::txt_lookup`] for those.
+    pub async fn txt_records(&self, key: impl IntoFqdn<'_>) -> crate::Result<Vec<String>> {
+        Ok(self
+            .resolver
+            .txt_lookup(Name::from_str_relaxed(key.into_fqdn().as_ref())?)
+            .await?
+            .as_lookup()
+            .record_iter()
+            .filter_map(|record| {
+                let txt_data = record.data()?.as_txt()?.txt_data();
+                let mut entry = Vec::with_capacity(txt_data.iter().map(|d| d.len()).sum());
+                for data in txt_data {
+                    entry.extend_from_slice(data);
+                }
+                String::from_utf8(entry).ok()
+            })
+            .collect())
+    }
+
+    /// Looks up and parses the TXT record of a name as any of the record
+    /// types supported by this crate, such as [`Dmarc`], [`MtaSts`] or
+    /// [`TlsRpt`]. Results, including errors, are cached until their TTL
+    /// expires.
+    ///
+    /// ```rust
+    /// # use mail_auth::{dmarc::Dmarc, Resolver};
+    /// # async fn f() -> mail_auth::Result<()> {
+    /// let resolver = Resolver::new_cloudflare_tls().unwrap();
+    /// let dmarc = resolver.txt_lookup::<Dmarc>("_dmarc.example.com").await?;
+    /// println!("Aggregate report addresses: {:?}", dmarc.rua());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn txt_lookup<'x, T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
         &self,
         key: impl IntoFqdn<'x>,
@@ -130,35 +270,15 @@ impl Resolver {
 
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(key.as_ref());
+            return self.mock_txt_lookup(key).await;
         }
 
         let txt_lookup = self
             .resolver
             .txt_lookup(Name::from_str_relaxed(key.as_ref())?)
             .await?;
-        let mut result = Err(Error::InvalidRecordType);
-        let records = txt_lookup.as_lookup().record_iter().filter_map(|r| {
-            let txt_data = r.data()?.as_txt()?.txt_data();
-            match txt_data.len() {
-                1 => Cow::from(txt_data[0].as_ref()).into(),
-                0 => None,
-                _ => {
-                    let mut entry = Vec::with_capacity(255 * txt_data.len());
-                    for data in txt_data {
-                        entry.extend_from_slice(data);
-                    }
-                    Cow::from(entry).into()
-                }
-            }
-        });
+        let result = parse_txt_lookup::<T>(txt_lookup.as_lookup());
 
-        for record in records {
-            result = T::parse(record.as_ref());
-            if result.is_ok() {
-                break;
-            }
-        }
         T::unwrap_txt(self.cache_txt.insert(
             key.into_owned(),
             result.into(),
@@ -166,6 +286,8 @@ impl Resolver {
         ))
     }
 
+    /// Returns the MX records of a name grouped by preference, lowest first.
+    /// Results are cached until their TTL expires.
     pub async fn mx_lookup<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<Arc<Vec<MX>>> {
         let key = key.into_fqdn();
         if let Some(value) = self.cache_mx.get(key.as_ref()) {
@@ -308,6 +430,8 @@ impl Resolver {
         }
     }
 
+    /// Returns the names an IP address reverse-maps to. Results are cached
+    /// until their TTL expires.
     pub async fn ptr_lookup<'x>(&self, addr: IpAddr) -> crate::Result<Arc<Vec<String>>> {
         if let Some(value) = self.cache_ptr.get(&addr) {
             return Ok(value);
@@ -337,42 +461,82 @@ impl Resolver {
             .insert(addr, Arc::new(ptr), ptr_lookup.valid_until()))
     }
 
+    /// Returns `true` if at least one A record exists for the name. Only A
+    /// records are queried, regardless of the client's IP version, as required
+    /// by the SPF `exists` mechanism (RFC 7208, Section 5.7).
     pub async fn exists<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<bool> {
-        #[cfg(any(test, feature = "test"))]
-        if true {
-            let key = key.into_fqdn().into_owned();
-            return match self.ipv4_lookup(key.as_str()).await {
-                Ok(_) => Ok(true),
-                Err(Error::DnsRecordNotFound(_)) => match self.ipv6_lookup(key.as_str()).await {
-                    Ok(_) => Ok(true),
-                    Err(Error::DnsRecordNotFound(_)) => Ok(false),
-                    Err(err) => Err(err),
-                },
-                Err(err) => Err(err),
-            };
+        match self.ipv4_lookup(key).await {
+            Ok(ips) => Ok(!ips.is_empty()),
+            Err(Error::DnsRecordNotFound(_)) => Ok(false),
+            Err(err) => Err(err),
         }
+    }
 
-        let key = key.into_fqdn();
-        match self
-            .resolver
-            .lookup_ip(Name::from_str_relaxed(key.as_ref())?)
-            .await
-        {
-            Ok(result) => Ok(result.as_lookup().record_iter().any(|r| {
-                r.data().map_or(false, |d| {
-                    matches!(d.record_type(), RecordType::A | RecordType::AAAA)
-                })
-            })),
-            Err(err) => {
-                if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
-                    Ok(false)
-                } else {
-                    Err(err.into())
-                }
+    #[cfg(any(test, feature = "test"))]
+    async fn mock_txt_lookup<T: UnwrapTxtRecord>(
+        &self,
+        key: Cow<'_, str>,
+    ) -> crate::Result<Arc<T>> {
+        let latency = {
+            let mut mock_dns = self.mock_dns.lock();
+            mock_dns.queries.push(key.to_string());
+            mock_dns.in_flight += 1;
+            mock_dns.max_in_flight = mock_dns.max_in_flight.max(mock_dns.in_flight);
+            mock_dns.latency
+        };
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+
+        let record = {
+            let mut mock_dns = self.mock_dns.lock();
+            mock_dns.in_flight -= 1;
+            mock_dns.txt.get(key.as_ref()).cloned()
+        };
+        match record {
+            Some((value, valid_until)) => {
+                T::unwrap_txt(self.cache_txt.insert(key.into_owned(), value, valid_until))
             }
+            None => mock_resolve(key.as_ref()),
         }
     }
 
+    /// Delays TXT lookups that miss the cache, simulating a slow DNS server.
+    /// The setting is shared with clones of this resolver.
+    #[cfg(any(test, feature = "test"))]
+    pub fn with_mock_latency(self, latency: Duration) -> Self {
+        self.mock_dns.lock().latency = latency;
+        self
+    }
+
+    /// Adds a TXT record that is served, and then cached, when a lookup of
+    /// the name misses the cache.
+    #[cfg(any(test, feature = "test"))]
+    pub fn mock_txt_add<'x>(
+        &self,
+        name: impl IntoFqdn<'x>,
+        value: impl Into<Txt>,
+        valid_until: std::time::Instant,
+    ) {
+        self.mock_dns
+            .lock()
+            .txt
+            .insert(name.into_fqdn().into_owned(), (value.into(), valid_until));
+    }
+
+    /// Returns the names of the TXT lookups that missed the cache, in order.
+    #[cfg(any(test, feature = "test"))]
+    pub fn mock_queries(&self) -> Vec<String> {
+        self.mock_dns.lock().queries.clone()
+    }
+
+    /// Returns the largest number of TXT lookups that were awaiting a mock
+    /// response at the same time.
+    #[cfg(any(test, feature = "test"))]
+    pub fn mock_max_in_flight(&self) -> usize {
+        self.mock_dns.lock().max_in_flight
+    }
+
     #[cfg(any(test, feature = "test"))]
     pub fn txt_add<'x>(
         &self,
@@ -581,31 +745,121 @@ pub trait IntoFqdn<'x> {
 
 impl<'x> IntoFqdn<'x> for String {
     fn into_fqdn(self) -> Cow<'x, str> {
-        if self.ends_with('.') {
-            self.to_lowercase().into()
-        } else {
-            format!("{}.", self.to_lowercase()).into()
-        }
+        fqdn(&self).into()
     }
 }
 
 impl<'x> IntoFqdn<'x> for &'x str {
     fn into_fqdn(self) -> Cow<'x, str> {
-        if self.ends_with('.') {
-            self.to_lowercase().into()
-        } else {
-            format!("{}.", self.to_lowercase()).into()
-        }
+        fqdn(self).into()
     }
 }
 
 impl<'x> IntoFqdn<'x> for &String {
     fn into_fqdn(self) -> Cow<'x, str> {
-        if self.ends_with('.') {
-            self.to_lowercase().into()
-        } else {
-            format!("{}.", self.to_lowercase()).into()
+        fqdn(self).into()
+    }
+}
+
+fn fqdn(name: &str) -> String {
+    let name = to_ascii_domain(name);
+    if name.ends_with('.') {
+        name.to_lowercase()
+    } else {
+        format!("{}.", name.to_lowercase())
+    }
+}
+
+/// Converts the Unicode labels of a domain name to their ASCII (punycode)
+/// form, as used in DNS queries. Names that are not valid IDNs are returned
+/// unchanged.
+#[cfg(feature = "idna")]
+pub(crate) fn to_ascii_domain(domain: &str) -> Cow<'_, str> {
+    if domain.is_ascii() {
+        Cow::Borrowed(domain)
+    } else {
+        idna::domain_to_ascii(domain).map_or(Cow::Borrowed(domain), Cow::Owned)
+    }
+}
+
+#[cfg(not(feature = "idna"))]
+pub(crate) fn to_ascii_domain(domain: &str) -> Cow<'_, str> {
+    Cow::Borrowed(domain)
+}
+
+// Parses the TXT records of a lookup. CNAME chains are followed by the resolver,
+// so any CNAME records in the answer are skipped. Records of the obsolete SPF RR
+// type (RFC 7208 section 3.1) are only used when no TXT records are present.
+pub(crate) fn parse_txt_lookup<T: TxtRecordParser>(lookup: &Lookup) -> crate::Result<T> {
+    let mut records = Vec::new();
+    let mut spf_records = Vec::new();
+
+    for record in lookup.record_iter() {
+        match record.data() {
+            Some(RData::TXT(txt)) => {
+                let txt_data = txt.txt_data();
+                match txt_data.len() {
+                    1 => records.push(Cow::from(txt_data[0].as_ref())),
+                    0 => (),
+                    _ => {
+                        let mut entry = Vec::with_capacity(255 * txt_data.len());
+                        for data in txt_data {
+                            entry.extend_from_slice(data);
+                        }
+                        records.push(Cow::from(entry));
+                    }
+                }
+            }
+            Some(RData::Unknown {
+                code: RecordType::Unknown(99),
+                rdata,
+            }) => {
+                // Same wire format as TXT, a sequence of length-prefixed strings
+                let mut entry = Vec::with_capacity(rdata.anything().len());
+                let mut data = rdata.anything();
+                while let Some((&len, rest)) = data.split_first() {
+                    let len = std::cmp::min(len as usize, rest.len());
+                    entry.extend_from_slice(&rest[..len]);
+                    data = &rest[len..];
+                }
+                spf_records.push(Cow::from(entry));
+            }
+            _ => (),
+        }
+    }
+
+    parse_txt_records(if records.is_empty() {
+        spf_records
+    } else {
+        records
+    })
+}
+
+// When more than one record parses, the one that sorts first is used so the
+// result does not depend on the answer order.
+pub(crate) fn parse_txt_records<T: TxtRecordParser>(
+    mut records: Vec<Cow<'_, [u8]>>,
+) -> crate::Result<T> {
+    records.sort_unstable();
+
+    let mut result = Err(Error::InvalidRecordType);
+    let mut num_records = 0;
+    for record in records {
+        let record = T::parse(record.as_ref());
+        if !matches!(record, Err(Error::InvalidRecordType)) {
+            num_records += 1;
         }
+        if result.is_err() {
+            result = record;
+        } else if T::allows_multiple_records() {
+            break;
+        }
+    }
+
+    if num_records > 1 && !T::allows_multiple_records() {
+        Err(Error::ParseError)
+    } else {
+        result
     }
 }
 
@@ -644,6 +898,17 @@ impl ToReverseName for IpAddr {
     }
 }
 
+/// Records served to lookups that miss the cache while testing.
+#[cfg(any(test, feature = "test"))]
+#[derive(Default)]
+pub(crate) struct MockDns {
+    pub(crate) latency: Duration,
+    pub(crate) txt: std::collections::HashMap<String, (Txt, std::time::Instant)>,
+    pub(crate) queries: Vec<String>,
+    pub(crate) in_flight: usize,
+    pub(crate) max_in_flight: usize,
+}
+
 #[cfg(any(test, feature = "test"))]
 pub fn mock_resolve<T>(domain: &str) -> crate::Result<T> {
     Err(if domain.contains("_parse_error.") {
@@ -661,7 +926,127 @@ pub fn mock_resolve<T>(domain: &str) -> crate::Result<T> {
 mod test {
     use std::net::IpAddr;
 
-    use crate::common::resolver::ToReverseName;
+    use hickory_resolver::{
+        config::{ResolverConfig, ResolverOpts},
+        error::ResolveError,
+        lookup::Lookup,
+        proto::{
+            op::Query,
+            rr::{
+                rdata::{CNAME, NULL, TXT},
+                RData, Record, RecordType,
+            },
+        },
+        Name,
+    };
+
+    use crate::{
+        common::{
+            parse::TxtRecordParser,
+            resolver::{parse_txt_lookup, ToReverseName},
+            verify::DomainKey,
+        },
+        spf::{Directive, Mechanism, Qualifier, Spf},
+        Error, Resolver,
+    };
+
+    fn txt_lookup(name: &str, records: impl IntoIterator<Item = RData>) -> Lookup {
+        let name = Name::from_ascii(name).unwrap();
+        Lookup::new_with_max_ttl(
+            Query::query(name.clone(), RecordType::TXT),
+            records
+                .into_iter()
+                .map(|rdata| Record::from_rdata(name.clone(), 300, rdata))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    #[test]
+    fn txt_lookup_records() {
+        let key_1 = "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=; t=y";
+        let key_2 = "v=DKIM1; k=ed25519; p=Ef4TDmspzTjh5wK8yqdY3AukmXaM6bL9bkn5kIR9cnw=; t=s";
+
+        // Selector published as a CNAME to the provider's key
+        let lookup = txt_lookup(
+            "default._domainkey.example.org.",
+            [
+                RData::CNAME(CNAME(Name::from_ascii("key.provider.net.").unwrap())),
+                RData::TXT(TXT::new(vec![key_1.to_string()])),
+            ],
+        );
+        assert_eq!(
+            parse_txt_lookup::<DomainKey>(&lookup).unwrap().f,
+            DomainKey::parse(key_1.as_bytes()).unwrap().f
+        );
+
+        // Multiple keys at the selector resolve to the same key regardless of the answer order
+        for records in [[key_1, key_2], [key_2, key_1]] {
+            let lookup = txt_lookup(
+                "default._domainkey.example.org.",
+                records.map(|r| RData::TXT(TXT::new(vec![r.to_string()]))),
+            );
+            assert_eq!(
+                parse_txt_lookup::<DomainKey>(&lookup).unwrap().f,
+                DomainKey::parse(key_1.as_bytes()).unwrap().f
+            );
+        }
+
+        // CNAME without a TXT record at the target
+        let lookup = txt_lookup(
+            "default._domainkey.example.org.",
+            [RData::CNAME(CNAME(
+                Name::from_ascii("key.provider.net.").unwrap(),
+            ))],
+        );
+        assert!(parse_txt_lookup::<DomainKey>(&lookup).is_err());
+
+        // Unparseable records are skipped
+        let lookup = txt_lookup(
+            "default._domainkey.example.org.",
+            [
+                RData::TXT(TXT::new(vec!["a=b; c=d; !!!".to_string()])),
+                RData::TXT(TXT::new(vec![key_2.to_string()])),
+            ],
+        );
+        assert_eq!(
+            parse_txt_lookup::<DomainKey>(&lookup).unwrap().f,
+            DomainKey::parse(key_2.as_bytes()).unwrap().f
+        );
+    }
+
+    #[test]
+    fn txt_lookup_spf_rr() {
+        let spf_rr = |record: &str| {
+            let mut rdata = vec![record.len() as u8];
+            rdata.extend_from_slice(record.as_bytes());
+            RData::Unknown {
+                code: RecordType::Unknown(99),
+                rdata: NULL::with(rdata),
+            }
+        };
+        let all = |qualifier| Directive::new(qualifier, Mechanism::All);
+
+        // TXT records take precedence over the obsolete SPF RR type
+        let lookup = txt_lookup(
+            "example.org.",
+            [
+                spf_rr("v=spf1 +all"),
+                RData::TXT(TXT::new(vec!["v=spf1 -all".to_string()])),
+            ],
+        );
+        assert_eq!(
+            parse_txt_lookup::<Spf>(&lookup).unwrap().directives,
+            vec![all(Qualifier::Fail)]
+        );
+
+        // SPF RR records are used when there are no TXT records
+        let lookup = txt_lookup("example.org.", [spf_rr("v=spf1 +all")]);
+        assert_eq!(
+            parse_txt_lookup::<Spf>(&lookup).unwrap().directives,
+            vec![all(Qualifier::Pass)]
+        );
+    }
 
     #[test]
     fn reverse_lookup_addr() {
@@ -679,4 +1064,40 @@ mod test {
             assert_eq!(addr.parse::<IpAddr>().unwrap().to_reverse_name(), expected);
         }
     }
+
+    #[tokio::test]
+    async fn resolver_bind_addr() {
+        for bind_addr in ["127.0.0.1", "::1"] {
+            assert!(Resolver::with_bind_addr(
+                ResolverConfig::cloudflare(),
+                ResolverOpts::default(),
+                bind_addr.parse().unwrap(),
+            )
+            .is_ok());
+        }
+    }
+
+    #[test]
+    fn error_conversions() {
+        fn lookup(err: impl Into<Error>) -> Result<(), Box<dyn std::error::Error>> {
+            Err(err.into())?
+        }
+
+        for (err, expected) in [
+            (
+                lookup(ResolveError::from("connection refused")),
+                "DNS resolution error: connection refused",
+            ),
+            (
+                lookup(std::io::Error::other("broken pipe")),
+                "I/O error: broken pipe",
+            ),
+            (
+                lookup(Error::UnsupportedQueryMethod),
+                "Unsupported query method in DKIM Signature",
+            ),
+        ] {
+            assert_eq!(err.unwrap_err().to_string(), expected);
+        }
+    }
 }
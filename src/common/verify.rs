@@ -10,7 +10,10 @@
 
 use std::net::IpAddr;
 
-use crate::{dkim::Canonicalization, Error, IprevOutput, IprevResult, Resolver};
+use crate::{
+    dkim::Canonicalization, AuthResults, AuthenticatedMessage, AuthenticationResults, DkimOutput,
+    DmarcOutput, Error, IprevOutput, IprevResult, Resolver, SpfOutput,
+};
 
 use super::crypto::{Algorithm, VerifyingKey};
 
@@ -72,12 +75,87 @@ impl Resolver {
     }
 }
 
+impl Resolver {
+    /// Verifies the SPF EHLO and MAIL FROM identities, the DKIM signatures and
+    /// the DMARC policy of a message received from `ip`. An empty `mail_from`
+    /// is checked as `postmaster@helo_domain` (RFC 7208 section 2.4).
+    pub async fn authenticate<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+    ) -> AuthResults<'x> {
+        let spf_ehlo = self.verify_spf_helo(ip, helo_domain, host_domain).await;
+        let spf_mail_from = self
+            .verify_spf_sender(ip, helo_domain, host_domain, mail_from)
+            .await;
+        let dkim = self.verify_dkim(message).await;
+        let dmarc = self
+            .verify_dmarc(
+                message,
+                &dkim,
+                mail_from
+                    .rsplit_once('@')
+                    .map_or(helo_domain, |(_, domain)| domain),
+                &spf_mail_from,
+            )
+            .await;
+
+        AuthResults {
+            ip,
+            helo_domain: helo_domain.to_string(),
+            host_domain: host_domain.to_string(),
+            mail_from: mail_from.to_string(),
+            header_from: message.from().to_string(),
+            spf_ehlo,
+            spf_mail_from,
+            dkim,
+            dmarc,
+        }
+    }
+}
+
 impl IprevOutput {
     pub fn result(&self) -> &IprevResult {
         &self.result
     }
 }
 
+impl<'x> AuthResults<'x> {
+    pub fn spf_ehlo(&self) -> &SpfOutput {
+        &self.spf_ehlo
+    }
+
+    pub fn spf_mail_from(&self) -> &SpfOutput {
+        &self.spf_mail_from
+    }
+
+    pub fn dkim(&self) -> &[DkimOutput<'x>] {
+        &self.dkim
+    }
+
+    pub fn dmarc(&self) -> &DmarcOutput {
+        &self.dmarc
+    }
+
+    /// Builds the Authentication-Results header for these results, using the
+    /// host domain as the authserv-id.
+    pub fn authentication_results(&self) -> AuthenticationResults<'_> {
+        AuthenticationResults::new(&self.host_domain)
+            .with_dkim_results(&self.dkim, &self.header_from)
+            .with_spf_ehlo_result(&self.spf_ehlo, self.ip, &self.helo_domain)
+            .with_spf_mailfrom_result(
+                &self.spf_mail_from,
+                self.ip,
+                &self.mail_from,
+                &self.helo_domain,
+            )
+            .with_dmarc_result(&self.dmarc)
+    }
+}
+
 impl DomainKey {
     pub(crate) fn verify<'a>(
         &self,
@@ -114,3 +192,136 @@ pub trait VerifySignature {
         key
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        common::{
+            crypto::{RsaKey, Sha256},
+            headers::HeaderWriter,
+            parse::TxtRecordParser,
+            verify::DomainKey,
+        },
+        dkim::DkimSigner,
+        dmarc::Dmarc,
+        spf::Spf,
+        AuthenticatedMessage, DkimResult, DmarcResult, Resolver, SpfResult,
+    };
+
+    const RSA_PRIVATE_KEY: &str = include_str!("../../resources/rsa-private.pem");
+
+    const RSA_PUBLIC_KEY: &str = concat!(
+        "v=DKIM1; t=s; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ",
+        "8AMIIBCgKCAQEAv9XYXG3uK95115mB4nJ37nGeNe2CrARm",
+        "1agrbcnSk5oIaEfMZLUR/X8gPzoiNHZcfMZEVR6bAytxUh",
+        "c5EvZIZrjSuEEeny+fFd/cTvcm3cOUUbIaUmSACj0dL2/K",
+        "wW0LyUaza9z9zor7I5XdIl1M53qVd5GI62XBB76FH+Q0bW",
+        "PZNkT4NclzTLspD/MTpNCCPhySM4Kdg5CuDczTH4aNzyS0",
+        "TqgXdtw6A4Sdsp97VXT9fkPW9rso3lrkpsl/9EQ1mR/DWK",
+        "6PBmRfIuSFuqnLKY6v/z2hXHxF7IoojfZLa2kZr9Aed4l9",
+        "WheQOTA19k5r2BmlRw/W9CrgCBo0Sdj+KQIDAQAB",
+    );
+
+    #[tokio::test]
+    async fn authenticate() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        let valid_until = Instant::now() + Duration::new(3600, 0);
+        resolver.txt_add(
+            "default._domainkey.example.org.",
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            valid_until,
+        );
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 -all").unwrap(),
+            valid_until,
+        );
+        resolver.txt_add(
+            "mx.example.org.",
+            Spf::parse(b"v=spf1 a -all").unwrap(),
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "mx.example.org.",
+            vec!["192.0.2.25".parse().unwrap()],
+            valid_until,
+        );
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=s; adkim=s").unwrap(),
+            valid_until,
+        );
+
+        let message = concat!(
+            "From: hello@example.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+        #[cfg(feature = "rust-crypto")]
+        let pk = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        let signature = DkimSigner::from_key(pk)
+            .domain("example.org")
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+            .sign(message.as_bytes())
+            .unwrap();
+        let message = format!("{}{message}", signature.to_header());
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+
+        let results = resolver
+            .authenticate(
+                &message,
+                "192.0.2.25".parse().unwrap(),
+                "mx.example.org",
+                "mx.example.net",
+                "sender@example.org",
+            )
+            .await;
+        assert_eq!(results.spf_ehlo().result(), SpfResult::Pass);
+        assert_eq!(results.spf_mail_from().result(), SpfResult::Pass);
+        assert_eq!(results.spf_mail_from().domain(), "example.org");
+        assert_eq!(results.dkim().len(), 1);
+        assert_eq!(results.dkim()[0].result(), &DkimResult::Pass);
+        assert_eq!(results.dmarc().spf_result(), &DmarcResult::Pass);
+        assert_eq!(results.dmarc().dkim_result(), &DmarcResult::Pass);
+
+        let header = results.authentication_results().to_header();
+        assert!(
+            header.starts_with("Authentication-Results: mx.example.net;\r\n\tdkim=pass"),
+            "{header}"
+        );
+        for property in [
+            "header.d=example.org",
+            "spf=pass",
+            "smtp.helo=mx.example.org",
+            "smtp.mailfrom=sender@example.org",
+            "dmarc=pass header.from=example.org policy.dmarc=reject",
+        ] {
+            assert!(header.contains(property), "{property} not in {header}");
+        }
+
+        // A null reverse-path is checked against the HELO domain
+        let results = resolver
+            .authenticate(
+                &message,
+                "192.0.2.25".parse().unwrap(),
+                "mx.example.org",
+                "mx.example.net",
+                "",
+            )
+            .await;
+        assert_eq!(results.spf_mail_from().result(), SpfResult::Pass);
+        assert_eq!(results.spf_mail_from().domain(), "mx.example.org");
+        assert_eq!(
+            results.dmarc().spf_result(),
+            &DmarcResult::Fail(crate::Error::NotAligned)
+        );
+        assert_eq!(results.dmarc().dkim_result(), &DmarcResult::Pass);
+    }
+}
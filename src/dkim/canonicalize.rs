@@ -8,15 +8,53 @@
  * except according to those terms.
  */
 
-use crate::common::headers::{HeaderStream, Writable, Writer};
+use crate::common::{
+    crypto::HashAlgorithm,
+    headers::{HeaderStream, Writable, Writer},
+};
 
 use super::{Canonicalization, Signature};
 
+/// Computes the hash of a canonicalized message body, so that it can be
+/// cached and compared against the `bh=` tag of any number of signatures
+/// sharing the same body canonicalization, hash algorithm and length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyHasher {
+    pub canonicalization: Canonicalization,
+    pub algorithm: HashAlgorithm,
+    pub length: u64,
+}
+
 pub struct CanonicalBody<'a> {
     canonicalization: Canonicalization,
     body: &'a [u8],
 }
 
+impl BodyHasher {
+    /// Creates a hasher for a body canonicalization, hash algorithm and body
+    /// length limit, where a length of 0 hashes the entire body.
+    pub fn new(canonicalization: Canonicalization, algorithm: HashAlgorithm, length: u64) -> Self {
+        BodyHasher {
+            canonicalization,
+            algorithm,
+            length,
+        }
+    }
+
+    /// Creates a hasher matching the `c=`, `a=` and `l=` tags of a signature.
+    pub fn for_signature(signature: &Signature) -> Self {
+        BodyHasher::new(signature.cb, signature.a.into(), signature.l)
+    }
+
+    /// Returns the hash of the canonicalized body.
+    pub fn hash(&self, body: &[u8]) -> Vec<u8> {
+        self.algorithm
+            .hash(self.canonicalization.canonical_body(body, self.length))
+            .as_ref()
+            .to_vec()
+    }
+}
+
 impl Writable for CanonicalBody<'_> {
     fn write(self, hasher: &mut impl Writer) {
         let mut crlf_seq = 0;
@@ -28,17 +66,10 @@ impl Writable for CanonicalBody<'_> {
 
                 for &ch in self.body {
                     match ch {
-                        b' ' | b'\t' => {
-                            while crlf_seq > 0 {
-                                hasher.write(b"\r\n");
-                                crlf_seq -= 1;
-                            }
-                            is_empty = false;
-                        }
                         b'\n' => {
                             crlf_seq += 1;
                         }
-                        b'\r' => {}
+                        b' ' | b'\t' | b'\r' => {}
                         _ => {
                             while crlf_seq > 0 {
                                 hasher.write(b"\r\n");
@@ -167,14 +198,16 @@ impl Signature {
         let mut signed_headers = Vec::with_capacity(self.h.len());
 
         while let Some((name, value)) = message.next_header() {
+            // Obsolete syntax allows whitespace and folding before the colon
+            let field_name = name.trim_ascii_end();
             if let Some(pos) = self
                 .h
                 .iter()
-                .position(|header| name.eq_ignore_ascii_case(header.as_bytes()))
+                .position(|header| field_name.eq_ignore_ascii_case(header.as_bytes()))
             {
                 headers.push((name, value));
                 found_headers[pos] = true;
-                signed_headers.push(std::str::from_utf8(name).unwrap().into());
+                signed_headers.push(std::str::from_utf8(field_name).unwrap().into());
             }
         }
 
@@ -211,10 +244,10 @@ impl<'a> Writable for CanonicalHeaders<'a> {
 mod test {
     use mail_builder::encoders::base64::base64_encode;
 
-    use super::{CanonicalBody, CanonicalHeaders};
+    use super::{BodyHasher, CanonicalBody, CanonicalHeaders};
     use crate::{
         common::{
-            crypto::{HashImpl, Sha256},
+            crypto::{HashAlgorithm, HashImpl, Sha256},
             headers::{HeaderIterator, Writable},
         },
         dkim::Canonicalization,
@@ -274,6 +307,27 @@ mod test {
                 (concat!("subject:hello\r\n"), ""),
                 ("Subject: hello\r\n", concat!("\r\n")),
             ),
+            (
+                // RFC 6376 section 3.4.5
+                concat!("A: X\r\n\r\n", " C \r\n", "D \t E\r\n", "\r\n", "\r\n"),
+                ("a:X\r\n", concat!(" C\r\n", "D E\r\n")),
+                ("A: X\r\n", concat!(" C \r\n", "D \t E\r\n")),
+            ),
+            (
+                concat!("A: X\r\n\r\n", "body\r\n", "  \r\n", "\t\r\n", "\r\n"),
+                ("a:X\r\n", "body\r\n"),
+                ("A: X\r\n", concat!("body\r\n", "  \r\n", "\t\r\n")),
+            ),
+            (
+                concat!("A: X\r\n\r\n", "a\r\n", " \t \r\n", "\r\n", "b \r\n"),
+                ("a:X\r\n", concat!("a\r\n", "\r\n", "\r\n", "b\r\n")),
+                ("A: X\r\n", concat!("a\r\n", " \t \r\n", "\r\n", "b \r\n")),
+            ),
+            (
+                concat!("A: X\r\n\r\n", " \t \r\n", "\r\n", "  "),
+                ("a:X\r\n", ""),
+                ("A: X\r\n", concat!(" \t \r\n", "\r\n", "  \r\n")),
+            ),
         ] {
             let mut header_iterator = HeaderIterator::new(message.as_bytes());
             let parsed_headers = (&mut header_iterator).collect::<Vec<_>>();
@@ -341,4 +395,52 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn dkim_canonicalize_simple_body() {
+        let hasher = BodyHasher::new(Canonicalization::Simple, HashAlgorithm::Sha256, 0);
+        for (body, canonical_body, hash) in [
+            ("", "\r\n", "frcCV1k9oG9oKj3dpUqdJg1PxRT2RSN/XKdLCPjaYaY="),
+            (
+                "\r\n",
+                "\r\n",
+                "frcCV1k9oG9oKj3dpUqdJg1PxRT2RSN/XKdLCPjaYaY=",
+            ),
+            (
+                "\r\n\r\n\r\n",
+                "\r\n",
+                "frcCV1k9oG9oKj3dpUqdJg1PxRT2RSN/XKdLCPjaYaY=",
+            ),
+            (
+                "abc",
+                "abc\r\n",
+                "VSuraGTHp7aaUC7RhUuSRcDhow8AiqoLKB2mJYX9sCU=",
+            ),
+            (
+                "abc\r\n\r\n",
+                "abc\r\n",
+                "VSuraGTHp7aaUC7RhUuSRcDhow8AiqoLKB2mJYX9sCU=",
+            ),
+            (
+                "abc\r\ndef",
+                "abc\r\ndef\r\n",
+                "UxdTL3XBl+Tt56ACUmPw4kYe2veG9CMR1WxYLqD1jwA=",
+            ),
+        ] {
+            let mut result = Vec::new();
+            Canonicalization::Simple
+                .canonical_body(body.as_bytes(), 0)
+                .write(&mut result);
+            assert_eq!(
+                String::from_utf8(result).unwrap(),
+                canonical_body,
+                "{body:?}"
+            );
+            assert_eq!(
+                String::from_utf8(base64_encode(&hasher.hash(body.as_bytes())).unwrap()).unwrap(),
+                hash,
+                "{body:?}"
+            );
+        }
+    }
 }
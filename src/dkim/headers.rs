@@ -12,7 +12,7 @@ use std::fmt::{Display, Formatter};
 
 use crate::common::headers::{HeaderWriter, Writer};
 
-use super::{Algorithm, Canonicalization, HashAlgorithm, Signature};
+use super::{Canonicalization, HashAlgorithm, Signature};
 
 impl Signature {
     pub(crate) fn write(&self, writer: &mut impl Writer, as_header: bool) {
@@ -22,11 +22,7 @@ impl Signature {
         };
         writer.write(header);
         writer.write(b"v=1; a=");
-        writer.write(match self.a {
-            Algorithm::RsaSha256 => b"rsa-sha256",
-            Algorithm::RsaSha1 => b"rsa-sha1",
-            Algorithm::Ed25519Sha256 => b"ed25519-sha256",
-        });
+        writer.write(self.a.as_str().as_bytes());
         for (tag, value) in [(&b"; s="[..], &self.s), (&b"; d="[..], &self.d)] {
             writer.write(tag);
             writer.write(value.as_bytes());
@@ -49,6 +45,15 @@ impl Signature {
         if self.r {
             writer.write(b"; r=y");
         }
+        if !self.q.is_empty() {
+            writer.write(b"; q=");
+            for (num, q) in self.q.iter().enumerate() {
+                if num > 0 {
+                    writer.write(b":");
+                }
+                writer.write(q.as_bytes());
+            }
+        }
 
         writer.write(b";");
         writer.write(new_line);
@@ -8,12 +8,15 @@
  * except according to those terms.
  */
 
+use mail_builder::encoders::base64::base64_encode;
+
 use crate::{
     arc::Set,
     common::{
-        crypto::{Algorithm, HashAlgorithm, SigningKey},
+        crypto::{digest_eq, Algorithm, HashAlgorithm, SigningKey},
         verify::VerifySignature,
     },
+    dmarc::DomainAlignment,
     ArcOutput, DkimOutput, DkimResult, Error, Version,
 };
 
@@ -26,7 +29,10 @@ pub mod parse;
 pub mod sign;
 pub mod verify;
 
+pub use verify::verify_with_key;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Canonicalization {
     #[default]
     Relaxed,
@@ -46,6 +52,7 @@ pub struct NeedHeaders;
 pub struct Done;
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Signature {
     pub v: u32,
     pub a: Algorithm,
@@ -55,6 +62,7 @@ pub struct Signature {
     pub bh: Vec<u8>,
     pub h: Vec<String>,
     pub z: Vec<String>,
+    pub q: Vec<String>,
     pub i: String,
     pub l: u64,
     pub x: u64,
@@ -66,6 +74,29 @@ pub struct Signature {
     pub cb: Canonicalization,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HeaderCoverage<'x> {
+    pub covered: Vec<&'x str>,
+    pub recommended: Vec<(&'static str, bool)>,
+}
+
+/// Headers that RFC 5322 allows at most once, checked for unsigned extra instances.
+pub(crate) const SINGLE_INSTANCE_HEADERS: [&str; 10] = [
+    "From",
+    "Sender",
+    "Reply-To",
+    "To",
+    "Cc",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+];
+
+/// Headers that should always be covered by a DKIM signature.
+pub const RECOMMENDED_SIGNED_HEADERS: [&str; 4] = ["From", "Subject", "Date", "Reply-To"];
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DomainKeyReport {
     pub(crate) ra: String,
@@ -93,6 +124,31 @@ pub(crate) const RR_UNKNOWN_TAG: u8 = 0x10;
 pub(crate) const RR_VERIFICATION: u8 = 0x20;
 pub(crate) const RR_EXPIRATION: u8 = 0x40;
 
+/// Default clock skew, in seconds, tolerated when checking `t=` and `x=`.
+pub const DEFAULT_CLOCK_SKEW: u64 = 300;
+
+/// Summaries over the per-signature outputs returned by
+/// [`Resolver::verify_dkim`](crate::Resolver::verify_dkim).
+pub trait DkimOutputs {
+    /// Returns `true` if at least one signature passed verification, which
+    /// is enough for the message to be treated as verified (RFC 6376 section 6.1).
+    fn any_pass(&self) -> bool;
+
+    /// Returns the `d=` domains of the passing signatures, without duplicates
+    /// and in header order.
+    fn pass_domains(&self) -> Vec<&str>;
+
+    /// Returns the passing signature most relevant to DMARC for the given
+    /// RFC5322.From domain: an exact `d=` match is preferred over a relaxed
+    /// (parent or subdomain) match, which is preferred over an unaligned pass.
+    /// Ties are broken by header order.
+    fn best_for_dmarc(&self, from_domain: &str) -> Option<&DkimOutput<'_>>;
+
+    /// Classifies the `d=` domain of each passing signature against the given
+    /// RFC5322.From domain, in header order.
+    fn alignments(&self, from_domain: &str) -> Vec<(&DkimOutput<'_>, DomainAlignment)>;
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[repr(u64)]
 pub(crate) enum Service {
@@ -156,6 +212,56 @@ impl Signature {
     pub fn identity(&self) -> &str {
         &self.i
     }
+
+    pub fn version(&self) -> u32 {
+        self.v
+    }
+
+    /// Returns the header and body canonicalizations of the `c=` tag.
+    pub fn canonicalization(&self) -> (Canonicalization, Canonicalization) {
+        (self.ch, self.cb)
+    }
+
+    /// Returns the names of the signed header fields of the `h=` tag.
+    pub fn signed_headers(&self) -> &[String] {
+        &self.h
+    }
+
+    pub fn body_hash(&self) -> &[u8] {
+        &self.bh
+    }
+
+    /// Returns the query methods of the `q=` tag.
+    pub fn query_methods(&self) -> &[String] {
+        &self.q
+    }
+
+    /// Returns `true` if the `q=` tag is absent or lists `dns/txt`,
+    /// the only query method defined by RFC 6376.
+    pub fn has_supported_query_method(&self) -> bool {
+        self.q.is_empty() || self.q.iter().any(|q| q.eq_ignore_ascii_case("dns/txt"))
+    }
+
+    /// Returns the `l=` body length limit, if any.
+    pub fn body_length(&self) -> Option<u64> {
+        Some(self.l).filter(|&l| l != 0)
+    }
+
+    /// Returns the `t=` signature timestamp, if any.
+    pub fn timestamp(&self) -> Option<u64> {
+        Some(self.t).filter(|&t| t != 0)
+    }
+
+    /// Returns the `x=` signature expiration, if any.
+    pub fn expiration(&self) -> Option<u64> {
+        Some(self.x).filter(|&x| x != 0)
+    }
+
+    /// Returns `true` if a computed body hash matches the `bh=` tag, comparing
+    /// in constant time.
+    pub fn body_hash_matches(&self, computed: &[u8]) -> bool {
+        digest_eq(&self.bh, computed)
+    }
 }
 
 impl<'x> DkimOutput<'x> {
@@ -230,9 +336,94 @@ impl<'x> DkimOutput<'x> {
         self.signature
     }
 
+    /// Returns the `d=` domain of the signature, if it could be parsed.
+    pub fn domain(&self) -> Option<&str> {
+        self.signature.map(|s| s.d.as_str())
+    }
+
+    /// Returns the `s=` selector of the signature, if it could be parsed.
+    pub fn selector(&self) -> Option<&str> {
+        self.signature.map(|s| s.s.as_str())
+    }
+
     pub fn failure_report_addr(&self) -> Option<&str> {
         self.report.as_deref()
     }
+
+    /// Returns the `Authentication-Results` properties of the signature
+    /// (RFC 8601 section 2.7.1 and RFC 6008), in the order they are written
+    /// to the header. `header.b` holds the first 8 characters of the
+    /// base64 encoded signature.
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = Vec::new();
+        if let Some(signature) = self.signature {
+            properties.push(("header.d", signature.d.clone()));
+            if !signature.i.is_empty() {
+                properties.push(("header.i", signature.i.clone()));
+            }
+            properties.push(("header.s", signature.s.clone()));
+            properties.push(("header.a", signature.a.as_str().to_string()));
+            if signature.b.len() >= 6 {
+                properties.push((
+                    "header.b",
+                    String::from_utf8(base64_encode(&signature.b[..6]).unwrap_or_default())
+                        .unwrap_or_default(),
+                ));
+            }
+        }
+        properties
+    }
+
+    /// Describes the result in plain English for troubleshooting, naming the
+    /// signing domain and selector along with the reason of a failure.
+    pub fn explain(&self) -> String {
+        let signature = match self.signature {
+            Some(signature) => format!("signature by {} (selector {})", signature.d, signature.s),
+            None => "signature".to_string(),
+        };
+        match &self.result {
+            DkimResult::Pass => format!("DKIM passed: {signature} verified"),
+            DkimResult::Neutral(err) => format!("DKIM neutral: {signature}: {err}"),
+            DkimResult::Fail(err) => format!("DKIM failed: {signature}: {err}"),
+            DkimResult::PermError(err) => format!("DKIM permanent error: {signature}: {err}"),
+            DkimResult::TempError(err) => format!("DKIM temporary error: {signature}: {err}"),
+            DkimResult::None => "DKIM none: message is not signed".to_string(),
+        }
+    }
+}
+
+impl DkimOutputs for [DkimOutput<'_>] {
+    fn any_pass(&self) -> bool {
+        self.iter().any(|o| o.result == DkimResult::Pass)
+    }
+
+    fn pass_domains(&self) -> Vec<&str> {
+        let mut domains: Vec<&str> = Vec::new();
+        for domain in self
+            .iter()
+            .filter(|o| o.result == DkimResult::Pass)
+            .filter_map(|o| o.domain())
+        {
+            if !domains.iter().any(|d| d.eq_ignore_ascii_case(domain)) {
+                domains.push(domain);
+            }
+        }
+        domains
+    }
+
+    fn best_for_dmarc(&self, from_domain: &str) -> Option<&DkimOutput<'_>> {
+        self.alignments(from_domain)
+            .into_iter()
+            .min_by_key(|(_, alignment)| *alignment)
+            .map(|(output, _)| output)
+    }
+
+    fn alignments(&self, from_domain: &str) -> Vec<(&DkimOutput<'_>, DomainAlignment)> {
+        self.iter()
+            .filter(|o| o.result == DkimResult::Pass)
+            .filter_map(|o| Some((o, DomainAlignment::classify(from_domain, o.domain()?))))
+            .collect()
+    }
 }
 
 impl<'x> ArcOutput<'x> {
@@ -243,6 +434,53 @@ impl<'x> ArcOutput<'x> {
     pub fn sets(&self) -> &[Set] {
         &self.set
     }
+
+    /// Returns the `ARC-Authentication-Results` recorded by the ARC set with
+    /// the given instance number, without its `i=` tag and with folding
+    /// whitespace collapsed. Results are only returned when the chain
+    /// validated, as they can't be trusted otherwise.
+    pub fn authentication_results(&self, instance: u32) -> Option<String> {
+        if self.result != DkimResult::Pass {
+            return None;
+        }
+        let results = self
+            .set
+            .iter()
+            .find(|set| set.results.header.i == instance)?
+            .results
+            .value;
+        let results = std::str::from_utf8(results).ok()?;
+        let results = match results.split_once(';') {
+            Some((i, results)) if i.trim().starts_with("i=") => results,
+            _ => results,
+        };
+        Some(
+            results
+                .split_ascii_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
+    /// Returns the `ARC-Authentication-Results` of the most recent ARC set,
+    /// which convey the results seen by the last intermediary that sealed
+    /// the message.
+    pub fn latest_authentication_results(&self) -> Option<String> {
+        self.authentication_results(self.set.len() as u32)
+    }
+
+    /// Returns the `d=` domain of each ARC-Seal, ordered by instance from
+    /// the first intermediary to the last, so they can be checked against a
+    /// list of trusted sealers. The list is empty unless the chain validated.
+    pub fn sealer_domains(&self) -> Vec<&str> {
+        if self.result != DkimResult::Pass {
+            return Vec::new();
+        }
+        self.set
+            .iter()
+            .map(|set| set.seal.header.d.as_str())
+            .collect()
+    }
 }
 
 impl From<Error> for DkimResult {
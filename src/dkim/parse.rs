@@ -8,7 +8,7 @@
  * except according to those terms.
  */
 
-use std::slice::Iter;
+use std::{slice::Iter, str::FromStr};
 
 use mail_parser::decoders::base64::base64_decode_stream;
 
@@ -44,6 +44,9 @@ const RS: u64 = (b'r' as u64) | (b's' as u64) << 8;
 const ALL: u64 = (b'a' as u64) | (b'l' as u64) << 8 | (b'l' as u64) << 16;
 
 impl Signature {
+    /// Parses the value of a DKIM-Signature header, without the header name.
+    /// A value missing any of the required `v`, `a`, `b`, `bh`, `d`, `h` or `s`
+    /// tags is rejected with [`Error::MissingParameters`].
     #[allow(clippy::while_let_on_iterator)]
     pub fn parse(header: &'_ [u8]) -> crate::Result<Self> {
         let mut signature = Signature {
@@ -56,6 +59,7 @@ impl Signature {
             bh: Vec::with_capacity(0),
             h: Vec::with_capacity(0),
             z: Vec::with_capacity(0),
+            q: Vec::with_capacity(0),
             l: 0,
             x: 0,
             t: 0,
@@ -67,6 +71,7 @@ impl Signature {
         };
         let header_len = header.len();
         let mut header = header.iter();
+        let mut has_algorithm = false;
 
         while let Some(key) = header.key() {
             match key {
@@ -78,6 +83,7 @@ impl Signature {
                 }
                 A => {
                     signature.a = header.algorithm()?;
+                    has_algorithm = true;
                 }
                 B => {
                     signature.b =
@@ -100,6 +106,7 @@ impl Signature {
                 T => signature.t = header.number().unwrap_or(0),
                 X => signature.x = header.number().unwrap_or(0),
                 Z => signature.z = header.headers_qp(),
+                Q => signature.q = header.items(),
                 R => signature.r = header.value() == Y,
                 ATPS => {
                     if signature.atps.is_none() {
@@ -121,7 +128,9 @@ impl Signature {
             }
         }
 
-        if !signature.d.is_empty()
+        if signature.v == 1
+            && has_algorithm
+            && !signature.d.is_empty()
             && !signature.s.is_empty()
             && !signature.b.is_empty()
             && !signature.bh.is_empty()
@@ -134,6 +143,14 @@ impl Signature {
     }
 }
 
+impl FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Signature::parse(s.as_bytes())
+    }
+}
+
 pub(crate) trait SignatureParser: Sized {
     fn canonicalization(
         &mut self,
@@ -304,6 +321,14 @@ impl TxtRecordParser for DomainKey {
     }
 }
 
+impl FromStr for DomainKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DomainKey::parse(s.as_bytes())
+    }
+}
+
 impl TxtRecordParser for DomainKeyReport {
     #[allow(clippy::while_let_on_iterator)]
     fn parse(header: &[u8]) -> crate::Result<Self> {
@@ -378,6 +403,14 @@ impl TxtRecordParser for DomainKeyReport {
     }
 }
 
+impl FromStr for DomainKeyReport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DomainKeyReport::parse(s.as_bytes())
+    }
+}
+
 impl TxtRecordParser for Atps {
     #[allow(clippy::while_let_on_iterator)]
     fn parse(header: &[u8]) -> crate::Result<Self> {
@@ -413,6 +446,14 @@ impl TxtRecordParser for Atps {
     }
 }
 
+impl FromStr for Atps {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Atps::parse(s.as_bytes())
+    }
+}
+
 impl DomainKey {
     pub fn has_flag(&self, flag: impl Into<u64>) -> bool {
         (self.f & flag.into()) != 0
@@ -463,13 +504,14 @@ mod test {
         common::{
             crypto::{Algorithm, R_HASH_SHA1, R_HASH_SHA256},
             parse::TxtRecordParser,
-            verify::DomainKey,
+            verify::{DomainKey, VerifySignature},
         },
         dkim::{
             Canonicalization, DomainKeyReport, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
             RR_POLICY, RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION, R_FLAG_MATCH_DOMAIN,
             R_FLAG_TESTING, R_SVC_ALL, R_SVC_EMAIL,
         },
+        Error,
     };
 
     #[test]
@@ -502,6 +544,7 @@ mod test {
                     .unwrap(),
                     h: vec!["Subject".into(), "To".into(), "From".into()],
                     z: vec![],
+                    q: vec![],
                     l: 0,
                     x: 0,
                     t: 311923920,
@@ -545,6 +588,7 @@ mod test {
                         "Subject:demo run".into(),
                         "Date:July 5, 2005 3:44:08 PM -0700".into(),
                     ],
+                    q: vec!["dns/txt".into()],
                     l: 0,
                     x: 1118006938,
                     t: 1117574938,
@@ -593,6 +637,7 @@ mod test {
                         "Message-ID".into(),
                     ],
                     z: vec![],
+                    q: vec!["dns/txt".into()],
                     l: 123,
                     x: 0,
                     t: 0,
@@ -614,6 +659,7 @@ mod test {
             assert_eq!(result.bh, expected_result.bh, "{signature:?}");
             assert_eq!(result.h, expected_result.h, "{signature:?}");
             assert_eq!(result.z, expected_result.z, "{signature:?}");
+            assert_eq!(result.q, expected_result.q, "{signature:?}");
             assert_eq!(result.l, expected_result.l, "{signature:?}");
             assert_eq!(result.x, expected_result.x, "{signature:?}");
             assert_eq!(result.t, expected_result.t, "{signature:?}");
@@ -622,6 +668,86 @@ mod test {
         }
     }
 
+    #[test]
+    fn dkim_signature_parse_required_tags() {
+        let signature = concat!(
+            "v=1; a=rsa-sha256; s=default; d=stalw.art; c=relaxed/relaxed; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; ",
+            "b=Du0rvdzNodI6b5bhlUaZZ+gpXJi0VwjY/3qL7lS0wzKutNVCbvdJuZObGdAcv; ",
+            "h=Subject:To:From"
+        );
+        assert!(Signature::parse(signature.as_bytes()).is_ok());
+
+        for tag in ["v=", "a=", "s=", "d=", "bh=", "b=", "h="] {
+            let signature = signature
+                .split("; ")
+                .filter(|t| !t.starts_with(tag))
+                .collect::<Vec<_>>()
+                .join("; ");
+            assert_eq!(
+                Signature::parse(signature.as_bytes()).unwrap_err(),
+                Error::MissingParameters,
+                "{signature:?}"
+            );
+        }
+
+        // Syntax errors are reported separately from missing tags
+        for (signature, expected) in [
+            (signature.replace("v=1", "v=2"), Error::UnsupportedVersion),
+            (
+                signature.replace("rsa-sha256", "rsa-md5"),
+                Error::UnsupportedAlgorithm,
+            ),
+            (signature.replace("bh=Qoi", "bh=!Qoi"), Error::Base64),
+        ] {
+            assert_eq!(
+                Signature::parse(signature.as_bytes()).unwrap_err(),
+                expected,
+                "{signature:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_signature_accessors() {
+        let signature = Signature::parse(
+            concat!(
+                "v=1; a=ed25519-sha256; c=simple/relaxed; d=example.org; s=brisbane; ",
+                "h=From:To:Subject; bh=AAAA; b=AAAB; l=120; t=1528637909; x=1528647909; ",
+                "i=jdoe@mail.example.org; q=dns/txt"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(signature.version(), 1);
+        assert_eq!(signature.algorithm(), Algorithm::Ed25519Sha256);
+        assert_eq!(
+            signature.canonicalization(),
+            (Canonicalization::Simple, Canonicalization::Relaxed)
+        );
+        assert_eq!(signature.domain(), "example.org");
+        assert_eq!(signature.selector(), "brisbane");
+        assert_eq!(signature.signed_headers(), ["From", "To", "Subject"]);
+        assert_eq!(signature.body_hash(), base64_decode(b"AAAA").unwrap());
+        assert_eq!(signature.signature(), base64_decode(b"AAAB").unwrap());
+        assert_eq!(signature.body_length(), Some(120));
+        assert_eq!(signature.timestamp(), Some(1528637909));
+        assert_eq!(signature.expiration(), Some(1528647909));
+        assert_eq!(signature.identity(), "jdoe@mail.example.org");
+
+        let signature =
+            Signature::parse(b"v=1; a=rsa-sha256; d=example.org; s=s; h=From; bh=AAAA; b=AAAA")
+                .unwrap();
+        assert_eq!(
+            signature.canonicalization(),
+            (Canonicalization::Simple, Canonicalization::Simple)
+        );
+        assert_eq!(signature.body_length(), None);
+        assert_eq!(signature.timestamp(), None);
+        assert_eq!(signature.expiration(), None);
+        assert_eq!(signature.identity(), "");
+    }
+
     #[test]
     fn dkim_record_parse() {
         for (record, expected_result) in [
@@ -19,7 +19,7 @@ use crate::{
         crypto::SigningKey,
         headers::{ChainedHeaderIterator, HeaderIterator, HeaderStream, Writable, Writer},
     },
-    Error,
+    AuthenticatedMessage, Error,
 };
 
 impl<T: SigningKey> DkimSigner<T, Done> {
@@ -50,6 +50,24 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         )
     }
 
+    /// Re-signs a message that was modified in transit, such as by a mailing
+    /// list, returning it with the DKIM-Signature headers selected by `strip`
+    /// removed and a fresh signature prepended. Results obtained from the
+    /// original message can then be preserved by sealing the re-signed
+    /// message with an `ArcSealer`.
+    pub fn resign(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        strip: impl Fn(&crate::Result<Signature>) -> bool,
+    ) -> crate::Result<Vec<u8>> {
+        let stripped = message.strip_dkim_signatures(strip);
+        let signature = self.sign(&stripped)?;
+        let mut resigned = Vec::with_capacity(stripped.len() + 512);
+        signature.write(&mut resigned, true);
+        resigned.extend_from_slice(&stripped);
+        Ok(resigned)
+    }
+
     fn sign_stream<'x>(
         &self,
         message: impl HeaderStream<'x>,
@@ -118,7 +136,10 @@ pub mod test {
             parse::TxtRecordParser,
             verify::DomainKey,
         },
-        dkim::{Atps, Canonicalization, DkimSigner, DomainKeyReport, HashAlgorithm, Signature},
+        dkim::{
+            verify_with_key, Atps, Canonicalization, DkimOutputs, DkimSigner, DomainKeyReport,
+            HashAlgorithm, Signature,
+        },
         AuthenticatedMessage, DkimOutput, DkimResult, Resolver,
     };
 
@@ -263,7 +284,7 @@ pub mod test {
                 .domain("example.com")
                 .selector("default")
                 .headers(["From", "To", "Subject"])
-                .agent_user_identifier("\"John Doe\" <jdoe@example.com>")
+                .agent_user_identifier("\"John Doe\"@example.com")
                 .sign(message.as_bytes())
                 .unwrap(),
             message,
@@ -296,7 +317,7 @@ pub mod test {
                 .domain("example.com")
                 .selector("default")
                 .headers(["From", "To", "Subject"])
-                .agent_user_identifier("\"John Doe\" <jdoe@example.com>")
+                .agent_user_identifier("\"John Doe\"@example.com")
                 .sign(empty_message.as_bytes())
                 .unwrap(),
             empty_message,
@@ -317,7 +338,7 @@ pub mod test {
                 .headers(["From", "To", "Subject"])
                 .header_canonicalization(Canonicalization::Simple)
                 .body_canonicalization(Canonicalization::Simple)
-                .agent_user_identifier("\"John Doe\" <jdoe@example.com>")
+                .agent_user_identifier("\"John Doe\"@example.com")
                 .sign(empty_message.as_bytes())
                 .unwrap(),
             empty_message,
@@ -390,6 +411,33 @@ pub mod test {
         )
         .await;
 
+        dbg!("Test AUID against a t=s key");
+        for (auid, expect) in [
+            ("jdoe@example.com", Ok(())),
+            ("@EXAMPLE.COM", Ok(())),
+            ("jdoe@mail.example.com", Err(super::Error::FailedAuidMatch)),
+            ("jdoe@example.com.org", Err(super::Error::FailedAuidMatch)),
+            ("jdoe@ample.com", Err(super::Error::FailedAuidMatch)),
+        ] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            verify(
+                &resolver,
+                DkimSigner::from_key(pk_rsa)
+                    .domain("example.com")
+                    .selector("default")
+                    .headers(["From", "To", "Subject"])
+                    .agent_user_identifier(auid)
+                    .sign(message.as_bytes())
+                    .unwrap(),
+                message,
+                expect,
+            )
+            .await;
+        }
+
         dbg!("Test expired signature and reporting");
         #[cfg(feature = "rust-crypto")]
         let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
@@ -484,6 +532,320 @@ pub mod test {
             Ok(()),
         )
         .await;
+
+        dbg!("Test unsigned duplicate Subject header");
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        verify(
+            &resolver,
+            DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .sign(message.as_bytes())
+                .unwrap(),
+            &format!("Subject: Urgent: wire transfer\r\n{message}"),
+            Err(super::Error::UnsignedHeaderInstance),
+        )
+        .await;
+
+        dbg!("Test obsolete folding in signed headers");
+        let message_obs = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject \r\n",
+            " : \"TPS\r\n",
+            " \r\n",
+            "\t Report\"\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+        for canonicalization in [Canonicalization::Relaxed, Canonicalization::Simple] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            let signature = DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .header_canonicalization(canonicalization)
+                .sign(message_obs.as_bytes())
+                .unwrap();
+            assert_eq!(signature.h, ["Subject", "To", "From"]);
+            verify(&resolver, signature, message_obs, Ok(())).await;
+        }
+
+        dbg!("Test relaxed/relaxed and simple/simple signatures on the same message");
+        let message_ws = concat!(
+            "From: bill@example.com\r\n",
+            "To:   jdoe@example.com\r\n",
+            "Subject:  TPS   Report \r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 2048);
+        for canonicalization in [Canonicalization::Relaxed, Canonicalization::Simple] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .header_canonicalization(canonicalization)
+                .body_canonicalization(canonicalization)
+                .sign(message_ws.as_bytes())
+                .unwrap()
+                .write(&mut signed_message, true);
+        }
+        let signed_len = signed_message.len();
+
+        // Both signatures verify on the original message
+        signed_message.extend_from_slice(message_ws.as_bytes());
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim.len(), 2);
+        for output in &dkim {
+            assert_eq!(output.result(), &DkimResult::Pass);
+        }
+        assert_eq!(dkim[0].signature().unwrap().ch, Canonicalization::Relaxed);
+        assert_eq!(dkim[1].signature().unwrap().ch, Canonicalization::Simple);
+
+        // Only the relaxed signature survives header whitespace and case changes
+        signed_message.truncate(signed_len);
+        signed_message.extend_from_slice(
+            message_ws
+                .replace("Subject:  TPS   Report \r\n", "SUBJECT: TPS Report\r\n")
+                .as_bytes(),
+        );
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(
+            dkim[1].result(),
+            &DkimResult::Fail(super::Error::FailedVerification)
+        );
+
+        dbg!("Test simple/simple signature on a message converted to bare LF");
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 1024);
+        DkimSigner::from_key(pk_rsa)
+            .domain("example.com")
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+            .header_canonicalization(Canonicalization::Simple)
+            .body_canonicalization(Canonicalization::Simple)
+            .sign(message_ws.as_bytes())
+            .unwrap()
+            .write(&mut signed_message, true);
+        signed_message.extend_from_slice(message_ws.as_bytes());
+        let lf_message = String::from_utf8(signed_message)
+            .unwrap()
+            .replace("\r\n", "\n");
+
+        let message = AuthenticatedMessage::parse(lf_message.as_bytes()).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(super::Error::FailedVerification)
+        );
+
+        let message = AuthenticatedMessage::parse_with_opts(lf_message.as_bytes(), true).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+
+        dbg!("Test a failing and a passing signature on the same message");
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "default._domainkey.example.org.".to_string(),
+            DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 2048);
+        for domain in ["example.org", "example.com"] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            DkimSigner::from_key(pk_rsa)
+                .domain(domain)
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .sign(message_ws.as_bytes())
+                .unwrap()
+                .write(&mut signed_message, true);
+        }
+        signed_message.extend_from_slice(message_ws.as_bytes());
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim.len(), 2);
+        assert_eq!(dkim[0].domain(), Some("example.org"));
+        assert_eq!(dkim[0].selector(), Some("default"));
+        assert_ne!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[1].domain(), Some("example.com"));
+        assert_eq!(dkim[1].result(), &DkimResult::Pass);
+        assert!(dkim.any_pass());
+        assert_eq!(dkim.pass_domains(), vec!["example.com"]);
+        assert!(!dkim[..1].any_pass());
+
+        dbg!("Test selecting the aligned signature out of two passing ones");
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "default._domainkey.example.net.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 2048);
+        for domain in ["example.net", "mail.example.com"] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            DkimSigner::from_key(pk_rsa)
+                .domain(domain)
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .sign(message_ws.as_bytes())
+                .unwrap()
+                .write(&mut signed_message, true);
+        }
+        signed_message.extend_from_slice(message_ws.as_bytes());
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "default._domainkey.mail.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim.pass_domains(), vec!["example.net", "mail.example.com"]);
+        assert_eq!(
+            dkim.best_for_dmarc("example.com").and_then(|o| o.domain()),
+            Some("mail.example.com")
+        );
+        assert_eq!(
+            dkim.best_for_dmarc("example.net").and_then(|o| o.domain()),
+            Some("example.net")
+        );
+        assert_eq!(
+            dkim.best_for_dmarc("example.org").and_then(|o| o.domain()),
+            Some("example.net")
+        );
+        assert_eq!(dkim[..0].best_for_dmarc("example.com"), None);
+
+        dbg!("Test simple/simple signature with b= refolded across three lines");
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 1024);
+        DkimSigner::from_key(pk_rsa)
+            .domain("example.com")
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+            .header_canonicalization(Canonicalization::Simple)
+            .body_canonicalization(Canonicalization::Simple)
+            .sign(message_ws.as_bytes())
+            .unwrap()
+            .write(&mut signed_message, true);
+        let header = String::from_utf8(signed_message).unwrap();
+        let (prefix, b) = header.rsplit_once("; b=").unwrap();
+        let b = b.trim_end_matches(";\r\n").replace("\r\n\t", "");
+        let (b1, rest) = b.split_at(b.len() / 3);
+        let (b2, b3) = rest.split_at(rest.len() / 2);
+        let refolded_message =
+            format!("{prefix}; b=\r\n\t{b1}\r\n\t {b2}\r\n\t{b3} ;\r\n{message_ws}");
+        let message = AuthenticatedMessage::parse(refolded_message.as_bytes()).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+
+        // Changing any byte outside of b= breaks the signature
+        let message_ = refolded_message.replacen("; b=\r\n", "; b =\r\n", 1);
+        let message = AuthenticatedMessage::parse(message_.as_bytes()).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(super::Error::FailedVerification)
+        );
+
+        dbg!("Test q= query methods");
+        for (q, expect) in [
+            (vec!["dns/txt", "other"], Ok(())),
+            (vec!["other"], Err(super::Error::UnsupportedQueryMethod)),
+        ] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+            let mut signer = DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"]);
+            signer.template.q = q.into_iter().map(String::from).collect();
+            verify(
+                &resolver,
+                signer.sign(message_ws.as_bytes()).unwrap(),
+                message_ws,
+                expect,
+            )
+            .await;
+        }
+
+        dbg!("Verify with an externally supplied public key");
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        let mut signed_message = Vec::with_capacity(message_ws.len() + 1024);
+        DkimSigner::from_key(pk_rsa)
+            .domain("offline.example")
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+            .sign(message_ws.as_bytes())
+            .unwrap()
+            .write(&mut signed_message, true);
+        let signed_len = signed_message.len();
+        signed_message.extend_from_slice(message_ws.as_bytes());
+        let rsa_key = DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap();
+        let ed_key = DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap();
+
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let signature = message.dkim_headers[0].header.as_ref().unwrap();
+        assert_eq!(
+            verify_with_key(&message, signature, &rsa_key),
+            DkimResult::Pass
+        );
+        assert_ne!(
+            verify_with_key(&message, signature, &ed_key),
+            DkimResult::Pass
+        );
+        assert_eq!(
+            verify_with_key(
+                &message,
+                &Signature {
+                    d: "other.example".to_string(),
+                    ..signature.clone()
+                },
+                &rsa_key
+            ),
+            DkimResult::None
+        );
+
+        signed_message.truncate(signed_len);
+        signed_message.extend_from_slice(message_ws.replace("ASAP", "tomorrow").as_bytes());
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+        let signature = message.dkim_headers[0].header.as_ref().unwrap();
+        assert_eq!(
+            verify_with_key(&message, signature, &rsa_key),
+            DkimResult::Neutral(super::Error::FailedBodyHashMatch)
+        );
     }
 
     pub async fn verify<'x>(
@@ -8,20 +8,22 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
+use std::{borrow::Cow, time::SystemTime};
 
 use crate::{
     common::{
         base32::Base32Writer,
-        headers::Writer,
+        headers::{Header, Writer},
+        message::to_crlf,
         verify::{DomainKey, VerifySignature},
     },
     is_within_pct, AuthenticatedMessage, DkimOutput, DkimResult, Error, Resolver,
 };
 
 use super::{
-    Atps, DomainKeyReport, Flag, HashAlgorithm, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
-    RR_SIGNATURE, RR_VERIFICATION,
+    Atps, DomainKeyReport, Flag, HeaderCoverage, Signature, DEFAULT_CLOCK_SKEW,
+    RECOMMENDED_SIGNED_HEADERS, RR_DNS, RR_EXPIRATION, RR_OTHER, RR_SIGNATURE, RR_VERIFICATION,
+    SINGLE_INSTANCE_HEADERS,
 };
 
 impl Resolver {
@@ -31,20 +33,24 @@ impl Resolver {
         &self,
         message: &'x AuthenticatedMessage<'x>,
     ) -> Vec<DkimOutput<'x>> {
-        self.verify_dkim_(
+        self.verify_dkim_at(
             message,
             SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
                 .unwrap_or(0),
+            DEFAULT_CLOCK_SKEW,
         )
         .await
     }
 
-    pub(crate) async fn verify_dkim_<'x>(
+    /// Verifies DKIM headers of an RFC5322 message at the given UNIX time,
+    /// tolerating `clock_skew` seconds when checking the `t=` and `x=` tags.
+    pub async fn verify_dkim_at<'x>(
         &self,
         message: &'x AuthenticatedMessage<'x>,
         now: u64,
+        clock_skew: u64,
     ) -> Vec<DkimOutput<'x>> {
         let mut output = Vec::with_capacity(message.dkim_headers.len());
         let mut report_requested = false;
@@ -58,14 +64,28 @@ impl Resolver {
                         report_requested = true;
                     }
 
-                    if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
-                        signature
-                    } else {
+                    if signature.x != 0
+                        && (signature.x <= signature.t
+                            || signature.x.saturating_add(clock_skew) <= now)
+                    {
                         output.push(
                             DkimOutput::neutral(Error::SignatureExpired).with_signature(signature),
                         );
                         continue;
+                    } else if signature.t > now.saturating_add(clock_skew) {
+                        output.push(
+                            DkimOutput::neutral(Error::SignatureInFuture).with_signature(signature),
+                        );
+                        continue;
+                    } else if !signature.has_supported_query_method() {
+                        output.push(
+                            DkimOutput::perm_err(Error::UnsupportedQueryMethod)
+                                .with_signature(signature),
+                        );
+                        continue;
                     }
+
+                    signature
                 }
                 Err(err) => {
                     output.push(DkimOutput::neutral(err.clone()));
@@ -74,18 +94,8 @@ impl Resolver {
             };
 
             // Validate body hash
-            let ha = HashAlgorithm::from(signature.a);
-            let bh = &message
-                .body_hashes
-                .iter()
-                .find(|(c, h, l, _)| c == &signature.cb && h == &ha && l == &signature.l)
-                .unwrap()
-                .3;
-
-            if bh != &signature.bh {
-                output.push(
-                    DkimOutput::neutral(Error::FailedBodyHashMatch).with_signature(signature),
-                );
+            if let Err(err) = message.verify_body_hash(signature) {
+                output.push(DkimOutput::neutral(err).with_signature(signature));
                 continue;
             }
 
@@ -98,18 +108,8 @@ impl Resolver {
                 }
             };
 
-            // Enforce t=s flag
-            if !signature.validate_auid(&record) {
-                output.push(DkimOutput::fail(Error::FailedAuidMatch).with_signature(signature));
-                continue;
-            }
-
-            // Hash headers
-            let dkim_hdr_value = header.value.strip_signature();
-            let mut headers = message.signed_headers(&signature.h, header.name, &dkim_hdr_value);
-
             // Verify signature
-            if let Err(err) = record.verify(&mut headers, signature, signature.ch) {
+            if let Err(err) = message.verify_signature(header, signature, &record) {
                 output.push(DkimOutput::fail(err).with_signature(signature));
                 continue;
             }
@@ -201,17 +201,24 @@ impl Resolver {
                             | Error::Io(_)
                             | Error::FailedVerification
                             | Error::FailedBodyHashMatch
-                            | Error::FailedAuidMatch => (record.rr & RR_VERIFICATION) != 0,
+                            | Error::ArcFailedBodyHashMatch(_)
+                            | Error::FailedAuidMatch
+                            | Error::UnsignedHeaderInstance => (record.rr & RR_VERIFICATION) != 0,
                             Error::Base64
                             | Error::UnsupportedVersion
                             | Error::UnsupportedAlgorithm
                             | Error::UnsupportedCanonicalization
+                            | Error::UnsupportedQueryMethod
                             | Error::UnsupportedKeyType
                             | Error::IncompatibleAlgorithms => (record.rr & RR_SIGNATURE) != 0,
-                            Error::SignatureExpired => (record.rr & RR_EXPIRATION) != 0,
+                            Error::SignatureExpired | Error::SignatureInFuture => {
+                                (record.rr & RR_EXPIRATION) != 0
+                            }
                             Error::DnsError(_)
                             | Error::DnsRecordNotFound(_)
                             | Error::InvalidRecordType
+                            | Error::InvalidCharacter
+                            | Error::InvalidMacro
                             | Error::ParseError
                             | Error::RevokedPublicKey => (record.rr & RR_DNS) != 0,
                             Error::MissingParameters
@@ -221,7 +228,8 @@ impl Resolver {
                             | Error::ArcInvalidCV
                             | Error::ArcHasHeaderTag
                             | Error::ArcBrokenChain
-                            | Error::NotAligned => (record.rr & RR_OTHER) != 0,
+                            | Error::NotAligned
+                            | Error::MultipleFromDomains => (record.rr & RR_OTHER) != 0,
                         };
 
                         if send_report {
@@ -239,9 +247,71 @@ impl Resolver {
     }
 }
 
+/// Verifies a DKIM signature of a message against a public key supplied by
+/// the caller instead of the one published in DNS. The `t=` and `x=` tags are
+/// not checked, which allows verifying messages offline or long after they
+/// were signed. Returns [`DkimResult::None`] if the signature does not belong
+/// to the message.
+pub fn verify_with_key(
+    message: &AuthenticatedMessage<'_>,
+    signature: &Signature,
+    public_key: &DomainKey,
+) -> DkimResult {
+    let header = match message
+        .dkim_headers
+        .iter()
+        .find(|h| h.header.as_ref() == Ok(signature))
+    {
+        Some(header) => header,
+        None => return DkimResult::None,
+    };
+
+    if let Err(err) = message.verify_body_hash(signature) {
+        DkimResult::Neutral(err)
+    } else if let Err(err) = message.verify_signature(header, signature, public_key) {
+        DkimResult::Fail(err)
+    } else {
+        DkimResult::Pass
+    }
+}
+
 impl<'x> AuthenticatedMessage<'x> {
+    fn verify_body_hash(&self, signature: &Signature) -> crate::Result<()> {
+        match self.body_hash(signature) {
+            Some(bh) if signature.body_hash_matches(bh) => Ok(()),
+            _ => Err(Error::FailedBodyHashMatch),
+        }
+    }
+
+    fn verify_signature(
+        &self,
+        header: &Header<'_, crate::Result<Signature>>,
+        signature: &Signature,
+        record: &DomainKey,
+    ) -> crate::Result<()> {
+        // Enforce t=s flag
+        if !signature.validate_auid(record) {
+            return Err(Error::FailedAuidMatch);
+        }
+
+        // Hash headers
+        let dkim_hdr_value = header.value.strip_signature();
+        let headers = self.signed_headers_normalized(&signature.h, header.name, &dkim_hdr_value);
+        let mut headers = headers.iter().map(|(name, value)| (*name, value.as_ref()));
+
+        // Verify signature
+        record.verify(&mut headers, signature, signature.ch)?;
+
+        // Reject unsigned instances of signed headers
+        if self.has_unsigned_header_instances(&signature.h) {
+            return Err(Error::UnsignedHeaderInstance);
+        }
+
+        Ok(())
+    }
+
     pub async fn get_canonicalized_header(&self) -> Result<Vec<u8>, Error> {
-        // Based on verify_dkim_ function
+        // Based on verify_dkim_at function
         // Iterate through possible DKIM headers
         let mut data = Vec::with_capacity(256);
         for header in &self.dkim_headers {
@@ -270,6 +340,42 @@ impl<'x> AuthenticatedMessage<'x> {
         Err(Error::FailedBodyHashMatch)
     }
 
+    /// Returns the header names covered by a DKIM signature and whether each
+    /// of the recommended headers is signed.
+    pub fn signed_header_coverage<'y>(&self, signature: &'y Signature) -> HeaderCoverage<'y> {
+        HeaderCoverage {
+            covered: signature.h.iter().map(|h| h.as_str()).collect(),
+            recommended: RECOMMENDED_SIGNED_HEADERS
+                .iter()
+                .map(|&name| {
+                    (
+                        name,
+                        signature.h.iter().any(|h| h.eq_ignore_ascii_case(name)),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if the message contains more instances of a signed single-instance
+    /// header than the signature covers, which allows an attacker to inject an
+    /// unsigned header without breaking the signature.
+    pub(crate) fn has_unsigned_header_instances(&self, headers: &[String]) -> bool {
+        SINGLE_INSTANCE_HEADERS.iter().any(|name| {
+            let num_signed = headers
+                .iter()
+                .filter(|h| h.eq_ignore_ascii_case(name))
+                .count();
+            num_signed > 0
+                && self
+                    .headers
+                    .iter()
+                    .filter(|(h, _)| h.trim_ascii_end().eq_ignore_ascii_case(name.as_bytes()))
+                    .count()
+                    > num_signed
+        })
+    }
+
     pub fn signed_headers<'z: 'x>(
         &'z self,
         headers: &'x [String],
@@ -295,7 +401,7 @@ impl<'x> AuthenticatedMessage<'x> {
                     .rev()
                     .enumerate()
                     .skip(*header_pos)
-                    .find(|(_, (mh, _))| h.as_bytes().eq_ignore_ascii_case(mh))
+                    .find(|(_, (mh, _))| h.as_bytes().eq_ignore_ascii_case(mh.trim_ascii_end()))
                 {
                     *header_pos = last_pos + 1;
                     Some(*result)
@@ -306,35 +412,39 @@ impl<'x> AuthenticatedMessage<'x> {
             })
             .chain([(dkim_hdr_name, dkim_hdr_value)])
     }
+
+    /// Returns the signed headers, converting bare LF line endings in their
+    /// values to CRLF when the message was parsed with normalization enabled.
+    pub(crate) fn signed_headers_normalized<'z: 'x>(
+        &'z self,
+        headers: &'x [String],
+        dkim_hdr_name: &'x [u8],
+        dkim_hdr_value: &'x [u8],
+    ) -> Vec<(&'x [u8], Cow<'x, [u8]>)> {
+        self.signed_headers(headers, dkim_hdr_name, dkim_hdr_value)
+            .map(|(name, value)| {
+                (
+                    name,
+                    if self.normalize_line_endings {
+                        to_crlf(value)
+                    } else {
+                        Cow::Borrowed(value)
+                    },
+                )
+            })
+            .collect()
+    }
 }
 
 impl Signature {
-    #[allow(clippy::while_let_on_iterator)]
     pub(crate) fn validate_auid(&self, record: &DomainKey) -> bool {
-        // Enforce t=s flag
-        if !self.i.is_empty() && record.has_flag(Flag::MatchDomain) {
-            let mut auid = self.i.chars();
-            let mut domain = self.d.chars();
-            while let Some(ch) = auid.next() {
-                if ch == '@' {
-                    break;
-                }
-            }
-            while let Some(ch) = auid.next() {
-                if let Some(dch) = domain.next() {
-                    if ch != dch {
-                        return false;
-                    }
-                } else {
-                    break;
-                }
-            }
-            if domain.next().is_some() {
-                return false;
-            }
-        }
-
-        true
+        // Enforce t=s flag, the AUID domain has to be d= and not a subdomain
+        self.i.is_empty()
+            || !record.has_flag(Flag::MatchDomain)
+            || self
+                .i
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| domain.eq_ignore_ascii_case(&self.d))
     }
 }
 
@@ -393,8 +503,8 @@ mod test {
 
     use crate::{
         common::{parse::TxtRecordParser, verify::DomainKey},
-        dkim::verify::Verifier,
-        AuthenticatedMessage, DkimResult, Resolver,
+        dkim::{verify::Verifier, HeaderCoverage},
+        AuthenticatedMessage, DkimResult, Error, Resolver,
     };
 
     #[tokio::test]
@@ -416,12 +526,112 @@ mod test {
             let raw_message = raw_message.replace('\n', "\r\n");
             let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
 
-            let dkim = resolver.verify_dkim_(&message, 1667843664).await;
+            let dkim = resolver.verify_dkim_at(&message, 1667862801, 0).await;
 
             assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+
+            if file_name.ends_with("002.txt") {
+                assert_eq!(
+                    dkim.last().unwrap().properties(),
+                    vec![
+                        ("header.d", "example.com".to_string()),
+                        ("header.i", "joe@football.example.com".to_string()),
+                        ("header.s", "newengland".to_string()),
+                        ("header.a", "rsa-sha256".to_string()),
+                        ("header.b", "Xh4Ujb2w".to_string()),
+                    ]
+                );
+                assert_eq!(
+                    dkim.last().unwrap().explain(),
+                    "DKIM passed: signature by example.com (selector newengland) verified"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_timestamps() {
+        let resolver = Resolver::new_system_conf().unwrap();
+
+        for (timestamps, now, expected_result) in [
+            (
+                "t=1000; x=2000",
+                5000,
+                DkimResult::Neutral(Error::SignatureExpired),
+            ),
+            (
+                "t=1000; x=2000",
+                2200,
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+            (
+                "t=2000; x=2000",
+                1500,
+                DkimResult::Neutral(Error::SignatureExpired),
+            ),
+            (
+                "t=10000",
+                5000,
+                DkimResult::Neutral(Error::SignatureInFuture),
+            ),
+            (
+                "t=5200",
+                5000,
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+        ] {
+            let raw_message = format!(
+                concat!(
+                    "DKIM-Signature: v=1; a=rsa-sha256; d=example.org; s=default; {}; ",
+                    "h=From; bh=AAAA; b=AAAA\r\n",
+                    "From: hello@example.org\r\n\r\nHi!\r\n"
+                ),
+                timestamps
+            );
+            let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+            assert_eq!(
+                resolver
+                    .verify_dkim_at(&message, now, 300)
+                    .await
+                    .pop()
+                    .unwrap()
+                    .result(),
+                &expected_result,
+                "{timestamps} at {now}"
+            );
         }
     }
 
+    #[test]
+    fn dkim_header_coverage() {
+        let raw_message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.org; s=default; ",
+            "h=From:To:Date:From; bh=AAAA; b=AAAA\r\n",
+            "From: hello@example.org\r\n",
+            "To: jdoe@example.org\r\n",
+            "Subject: Unsigned subject\r\n",
+            "Date: Mon, 7 Nov 2022 18:00:00 +0000\r\n",
+            "\r\n",
+            "Hi!\r\n"
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let signature = message.dkim_headers[0].header.as_ref().unwrap();
+
+        assert_eq!(
+            message.signed_header_coverage(signature),
+            HeaderCoverage {
+                covered: vec!["From", "To", "Date", "From"],
+                recommended: vec![
+                    ("From", true),
+                    ("Subject", false),
+                    ("Date", true),
+                    ("Reply-To", false)
+                ],
+            }
+        );
+    }
+
     #[test]
     fn dkim_strip_signature() {
         for (value, stripped_value) in [
@@ -429,6 +639,14 @@ mod test {
             ("bh=B64b=;h=From;b=abc\r\n", "bh=B64b=;h=From;b="),
             ("h=From; b = abc\r\ndef\r\n; v=1\r\n", "h=From; b =; v=1"),
             ("B\r\n=abc;v=1\r\n", "B\r\n=;v=1"),
+            (
+                "v=1; bh=B64=; h=From:To;\r\n\tb=abc\r\n\t def\r\n\t ghi\r\n",
+                "v=1; bh=B64=; h=From:To;\r\n\tb=",
+            ),
+            (
+                "v=1; b= abc\r\n\tdef\r\n\tghi ; bh=B64=;\r\n\th=From\r\n",
+                "v=1; b=; bh=B64=;\r\n\th=From",
+            ),
         ] {
             assert_eq!(
                 String::from_utf8(value.as_bytes().strip_signature()).unwrap(),
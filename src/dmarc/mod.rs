@@ -18,6 +18,7 @@ pub mod parse;
 pub mod verify;
 
 #[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Dmarc {
     pub v: Version,
     pub adkim: Alignment,
@@ -43,12 +44,26 @@ pub struct URI {
 }
 
 #[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Alignment {
     Relaxed,
     Strict,
 }
 
+/// How an authenticated domain relates to the RFC5322.From domain, ordered
+/// from the closest to the weakest relation.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DomainAlignment {
+    /// Both domains are identical.
+    Strict,
+    /// One domain is a subdomain of the other.
+    Relaxed,
+    /// The domains are unrelated.
+    Unaligned,
+}
+
 #[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Psd {
     Yes,
     No,
@@ -56,6 +71,7 @@ pub enum Psd {
 }
 
 #[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Report {
     All,
     Any,
@@ -65,6 +81,7 @@ pub enum Report {
 }
 
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Policy {
     None,
     Quarantine,
@@ -201,6 +218,39 @@ impl DmarcOutput {
             _ => None,
         }
     }
+
+    /// Describes the result in plain English for troubleshooting, naming the
+    /// identifiers that aligned or the reason of a failure along with the
+    /// policy published by the domain.
+    pub fn explain(&self) -> String {
+        let domain = &self.domain;
+        match (&self.spf_result, &self.dkim_result) {
+            (DmarcResult::Pass, DmarcResult::Pass) => {
+                format!("DMARC passed for {domain}: SPF and DKIM aligned")
+            }
+            (DmarcResult::Pass, _) => format!("DMARC passed for {domain}: SPF aligned"),
+            (_, DmarcResult::Pass) => format!("DMARC passed for {domain}: DKIM aligned"),
+            (DmarcResult::TempError(err), _) | (_, DmarcResult::TempError(err)) => {
+                format!("DMARC temporary error for {domain}: {err}")
+            }
+            (DmarcResult::PermError(err), _) | (_, DmarcResult::PermError(err)) => {
+                format!("DMARC permanent error for {domain}: {err}")
+            }
+            (DmarcResult::Fail(err), _) | (_, DmarcResult::Fail(err)) => {
+                let reason = match err {
+                    Error::NotAligned => "neither SPF nor DKIM aligned".to_string(),
+                    err => err.to_string(),
+                };
+                format!(
+                    "DMARC failed for {domain}: {reason}; policy is {}",
+                    self.policy
+                )
+            }
+            (DmarcResult::None, DmarcResult::None) => {
+                format!("DMARC none for {domain}: no DMARC record found")
+            }
+        }
+    }
 }
 
 impl Dmarc {
@@ -8,7 +8,7 @@
  * except according to those terms.
  */
 
-use std::slice::Iter;
+use std::{slice::Iter, str::FromStr};
 
 use mail_parser::decoders::quoted_printable::quoted_printable_decode_char;
 
@@ -108,6 +108,14 @@ impl TxtRecordParser for Dmarc {
     }
 }
 
+impl FromStr for Dmarc {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Dmarc::parse(s.as_bytes())
+    }
+}
+
 pub(crate) trait DMARCParser: Sized {
     fn alignment(&mut self) -> crate::Result<Alignment>;
     fn report(&mut self) -> crate::Result<Report>;
@@ -11,11 +11,53 @@
 use std::sync::Arc;
 
 use crate::{
-    AuthenticatedMessage, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error, Resolver,
-    SpfOutput, SpfResult,
+    common::resolver::to_ascii_domain, dkim::DkimOutputs, AuthenticatedMessage, DkimOutput,
+    DkimResult, DmarcOutput, DmarcResult, Error, Resolver, SpfOutput, SpfResult,
 };
 
-use super::{Alignment, Dmarc, URI};
+use super::{Alignment, Dmarc, DomainAlignment, Policy, URI};
+
+impl DomainAlignment {
+    /// Classifies the alignment of `domain` with the RFC5322.From domain,
+    /// ignoring case, trailing dots and the encoding of internationalized names.
+    /// Domains sharing no more than a public suffix, such as `com` or `co.uk`,
+    /// are not aligned.
+    pub fn classify(from_domain: &str, domain: &str) -> Self {
+        let from_domain = to_ascii_domain(from_domain.trim_end_matches('.')).to_lowercase();
+        let domain = to_ascii_domain(domain.trim_end_matches('.')).to_lowercase();
+        if domain == from_domain {
+            DomainAlignment::Strict
+        } else if (domain.ends_with(&format!(".{from_domain}")) && !is_public_suffix(&from_domain))
+            || (from_domain.ends_with(&format!(".{domain}")) && !is_public_suffix(&domain))
+        {
+            DomainAlignment::Relaxed
+        } else {
+            DomainAlignment::Unaligned
+        }
+    }
+}
+
+/// Ranks policies from the most lenient to the strictest.
+fn policy_strictness(policy: Policy) -> u8 {
+    match policy {
+        Policy::Reject => 2,
+        Policy::Quarantine => 1,
+        Policy::None | Policy::Unspecified => 0,
+    }
+}
+
+/// Approximates the public suffix list: single labels and second-level
+/// registries under country code TLDs, such as `co.uk`, are public suffixes.
+fn is_public_suffix(domain: &str) -> bool {
+    let mut labels = domain.rsplit('.');
+    match (labels.next(), labels.next(), labels.next()) {
+        (_, None, _) => true,
+        (Some(tld), Some(sld), None) => {
+            tld.len() == 2 && matches!(sld, "ac" | "co" | "com" | "edu" | "gov" | "net" | "org")
+        }
+        _ => false,
+    }
+}
 
 impl Resolver {
     /// Verifies the DMARC policy of an RFC5322.From domain
@@ -27,21 +69,35 @@ impl Resolver {
         spf_output: &SpfOutput,
     ) -> DmarcOutput {
         // Extract RFC5322.From
-        let mut from_domain = "";
-        for from in &message.from {
-            if let Some((_, domain)) = from.rsplit_once('@') {
-                if from_domain.is_empty() {
-                    from_domain = domain;
-                } else if from_domain != domain {
-                    // Multi-valued RFC5322.From header fields with multiple
-                    // domains MUST be exempt from DMARC checking.
-                    return DmarcOutput::default();
+        let from_domain = match message.from_domain() {
+            Ok(Some(from_domain)) => from_domain,
+            Ok(None) => return DmarcOutput::default(),
+            Err(from_domain) => {
+                // Multi-valued RFC5322.From header fields spanning several domains
+                // fail DMARC. The policy of every author domain is looked up and
+                // the strictest one applies (RFC 7489 section 6.6.1).
+                let err = DmarcResult::Fail(Error::MultipleFromDomains);
+                let mut output = DmarcOutput::default()
+                    .with_domain(from_domain)
+                    .with_dkim_result(err.clone())
+                    .with_spf_result(err);
+                for domain in message.from_domains() {
+                    if let Ok(Some(dmarc)) = self.dmarc_tree_walk(domain).await {
+                        if output.record.is_none()
+                            || policy_strictness(dmarc.p) > policy_strictness(output.policy)
+                        {
+                            output = DmarcOutput {
+                                policy: dmarc.p,
+                                ..output
+                            }
+                            .with_domain(domain)
+                            .with_record(dmarc);
+                        }
+                    }
                 }
+                return output;
             }
-        }
-        if from_domain.is_empty() {
-            return DmarcOutput::default();
-        }
+        };
 
         // Obtain DMARC policy
         let dmarc = match self.dmarc_tree_walk(from_domain).await {
@@ -67,47 +123,36 @@ impl Resolver {
         let has_dkim_pass = dkim_output.iter().any(|o| o.result == DkimResult::Pass);
         if spf_output.result == SpfResult::Pass || has_dkim_pass {
             // Check SPF alignment
-            let from_subdomain = format!(".{from_domain}");
             if spf_output.result == SpfResult::Pass {
-                output.spf_result = if mail_from_domain == from_domain {
-                    DmarcResult::Pass
-                } else if dmarc.aspf == Alignment::Relaxed
-                    && mail_from_domain.ends_with(&from_subdomain)
-                    || from_domain.ends_with(&format!(".{mail_from_domain}"))
-                {
-                    output.policy = dmarc.sp;
-                    DmarcResult::Pass
-                } else {
-                    DmarcResult::Fail(Error::NotAligned)
+                output.spf_result = match DomainAlignment::classify(from_domain, mail_from_domain) {
+                    DomainAlignment::Strict => DmarcResult::Pass,
+                    DomainAlignment::Relaxed if dmarc.aspf == Alignment::Relaxed => {
+                        output.policy = dmarc.sp;
+                        DmarcResult::Pass
+                    }
+                    _ => DmarcResult::Fail(Error::NotAligned),
                 };
             }
 
             // Check DKIM alignment
             if has_dkim_pass {
-                output.dkim_result = if dkim_output.iter().any(|o| {
-                    o.result == DkimResult::Pass && o.signature.as_ref().unwrap().d.eq(from_domain)
-                }) {
-                    DmarcResult::Pass
-                } else if dmarc.adkim == Alignment::Relaxed
-                    && dkim_output.iter().any(|o| {
-                        o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
-                    })
-                {
-                    output.policy = dmarc.sp;
-                    DmarcResult::Pass
-                } else {
-                    if dkim_output.iter().any(|o| {
-                        o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
-                    }) {
+                let alignment = dkim_output
+                    .alignments(from_domain)
+                    .into_iter()
+                    .map(|(_, alignment)| alignment)
+                    .min()
+                    .unwrap_or(DomainAlignment::Unaligned);
+                output.dkim_result = match alignment {
+                    DomainAlignment::Strict => DmarcResult::Pass,
+                    DomainAlignment::Relaxed => {
                         output.policy = dmarc.sp;
+                        if dmarc.adkim == Alignment::Relaxed {
+                            DmarcResult::Pass
+                        } else {
+                            DmarcResult::Fail(Error::NotAligned)
+                        }
                     }
-                    DmarcResult::Fail(Error::NotAligned)
+                    DomainAlignment::Unaligned => DmarcResult::Fail(Error::NotAligned),
                 };
             }
         }
@@ -194,8 +239,8 @@ mod test {
 
     use crate::{
         common::parse::TxtRecordParser,
-        dkim::Signature,
-        dmarc::{Dmarc, Policy, URI},
+        dkim::{DkimOutputs, Signature},
+        dmarc::{Dmarc, DomainAlignment, Policy, URI},
         AuthenticatedMessage, DkimOutput, DkimResult, DmarcResult, Error, Resolver, SpfOutput,
         SpfResult,
     };
@@ -336,6 +381,11 @@ mod test {
                 domain: mail_from_domain.to_string(),
                 report: None,
                 explanation: None,
+                matched: None,
+                ip: None,
+                valid_until: None,
+                policy_hint: None,
+                trusted_forwarder: false,
             };
             let result = resolver
                 .verify_dmarc(&auth_message, &[dkim], mail_from_domain, &spf)
@@ -346,6 +396,236 @@ mod test {
         }
     }
 
+    #[test]
+    fn dkim_alignments() {
+        let signatures = [
+            "mailer.thirdparty.com",
+            "example.com",
+            "mail.EXAMPLE.com.",
+            "com",
+            "example.org",
+        ]
+        .map(|d| Signature {
+            d: d.into(),
+            ..Default::default()
+        });
+        let mut dkim = signatures
+            .iter()
+            .map(|signature| DkimOutput {
+                result: DkimResult::Pass,
+                signature: signature.into(),
+                report: None,
+                is_atps: false,
+            })
+            .collect::<Vec<_>>();
+        dkim[4].result = DkimResult::Fail(Error::FailedVerification);
+
+        assert_eq!(
+            dkim.alignments("example.com")
+                .into_iter()
+                .map(|(output, alignment)| (output.domain().unwrap(), alignment))
+                .collect::<Vec<_>>(),
+            vec![
+                ("mailer.thirdparty.com", DomainAlignment::Unaligned),
+                ("example.com", DomainAlignment::Strict),
+                ("mail.EXAMPLE.com.", DomainAlignment::Relaxed),
+                ("com", DomainAlignment::Unaligned),
+            ]
+        );
+        assert_eq!(
+            dkim[..1].alignments("example.com")[0].1,
+            DomainAlignment::Unaligned
+        );
+        assert_eq!(
+            DomainAlignment::classify("example.com", "notexample.com"),
+            DomainAlignment::Unaligned
+        );
+        assert_eq!(
+            DomainAlignment::classify("example.co.uk", "co.uk"),
+            DomainAlignment::Unaligned
+        );
+        assert_eq!(
+            DomainAlignment::classify("example.co.uk", "mail.example.co.uk"),
+            DomainAlignment::Relaxed
+        );
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_alignment_agrees() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.co.uk.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=r; adkim=r").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let message = AuthenticatedMessage::parse(b"From: hello@example.co.uk\r\n\r\n").unwrap();
+
+        // DMARC evaluation and DKIM alignment reporting classify domains alike
+        for domain in [
+            "example.co.uk",
+            "EXAMPLE.co.uk.",
+            "Mail.Example.CO.UK",
+            "co.uk",
+            "uk",
+            "other.co.uk",
+        ] {
+            let signature = Signature {
+                d: domain.into(),
+                ..Default::default()
+            };
+            let dkim = [DkimOutput {
+                result: DkimResult::Pass,
+                signature: (&signature).into(),
+                report: None,
+                is_atps: false,
+            }];
+            let spf = SpfOutput {
+                result: SpfResult::Pass,
+                domain: domain.to_string(),
+                report: None,
+                explanation: None,
+                matched: None,
+                ip: None,
+                valid_until: None,
+                policy_hint: None,
+                trusted_forwarder: false,
+            };
+            let result = resolver.verify_dmarc(&message, &dkim, domain, &spf).await;
+            let aligned = dkim.alignments("example.co.uk")[0].1 != DomainAlignment::Unaligned;
+            assert_eq!(result.dkim_result == DmarcResult::Pass, aligned, "{domain}");
+            assert_eq!(result.spf_result == DmarcResult::Pass, aligned, "{domain}");
+            assert_eq!(
+                aligned,
+                !matches!(domain, "co.uk" | "uk" | "other.co.uk"),
+                "{domain}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_multiple_from() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=quarantine").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message = AuthenticatedMessage::parse(
+            b"From: hello@example.org, hello@EXAMPLE.org, bye@example.net\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(auth_message.from_domain(), Err("example.org"));
+        assert_eq!(
+            auth_message.from_domains().collect::<Vec<_>>(),
+            ["example.org", "example.net"]
+        );
+        let signature = Signature {
+            d: "example.org".into(),
+            ..Default::default()
+        };
+        let dkim = DkimOutput {
+            result: DkimResult::Pass,
+            signature: (&signature).into(),
+            report: None,
+            is_atps: false,
+        };
+        let spf = SpfOutput {
+            result: SpfResult::Pass,
+            domain: "example.org".to_string(),
+            report: None,
+            explanation: None,
+            matched: None,
+            ip: None,
+            valid_until: None,
+            policy_hint: None,
+            trusted_forwarder: false,
+        };
+        let result = resolver
+            .verify_dmarc(&auth_message, &[dkim], "example.org", &spf)
+            .await;
+        assert_eq!(result.domain(), "example.org");
+        assert_eq!(
+            result.dkim_result,
+            DmarcResult::Fail(Error::MultipleFromDomains)
+        );
+        assert_eq!(
+            result.spf_result,
+            DmarcResult::Fail(Error::MultipleFromDomains)
+        );
+        assert_eq!(result.policy, Policy::Quarantine);
+        assert!(result.dmarc_record().is_some());
+        assert_eq!(
+            result.explain(),
+            "DMARC failed for example.org: Multiple RFC5322.From domains; policy is quarantine"
+        );
+
+        // The strictest policy among the author domains applies
+        resolver.txt_add(
+            "_dmarc.example.net.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "example.org", &spf)
+            .await;
+        assert_eq!(result.domain(), "example.net");
+        assert_eq!(result.policy, Policy::Reject);
+        assert_eq!(
+            result.explain(),
+            "DMARC failed for example.net: Multiple RFC5322.From domains; policy is reject"
+        );
+
+        let auth_message =
+            AuthenticatedMessage::parse(b"From: hello@example.org, hello@EXAMPLE.org\r\n\r\n")
+                .unwrap();
+        assert_eq!(auth_message.from_domain(), Ok(Some("example.org")));
+        let auth_message = AuthenticatedMessage::parse(b"Subject: test\r\n\r\n").unwrap();
+        assert_eq!(auth_message.from_domain(), Ok(None));
+    }
+
+    #[cfg(feature = "idna")]
+    #[tokio::test]
+    async fn dmarc_verify_idn() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.xn--mller-kva.de.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=s; adkim=r").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message =
+            AuthenticatedMessage::parse("From: info@müller.de\r\n\r\n".as_bytes()).unwrap();
+        let signature = Signature {
+            d: "mail.MÜLLER.de".into(),
+            ..Default::default()
+        };
+        let dkim = DkimOutput {
+            result: DkimResult::Pass,
+            signature: (&signature).into(),
+            report: None,
+            is_atps: false,
+        };
+        let spf = SpfOutput {
+            result: SpfResult::Pass,
+            domain: "xn--mller-kva.de".to_string(),
+            report: None,
+            explanation: None,
+            matched: None,
+            ip: None,
+            valid_until: None,
+            policy_hint: None,
+            trusted_forwarder: false,
+        };
+        let result = resolver
+            .verify_dmarc(&auth_message, &[dkim], "xn--mller-kva.de", &spf)
+            .await;
+        assert_eq!(result.dkim_result, DmarcResult::Pass);
+        assert_eq!(result.spf_result, DmarcResult::Pass);
+        assert_eq!(result.policy, Policy::Reject);
+    }
+
     #[tokio::test]
     async fn dmarc_verify_report_address() {
         let resolver = Resolver::new_system_conf().unwrap();
@@ -263,7 +263,7 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::Arc,
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
 use arc::Set;
@@ -276,7 +276,7 @@ use hickory_resolver::{
 };
 use mta_sts::{MtaSts, TlsRpt};
 use parking_lot::Mutex;
-use spf::{Macro, Spf};
+use spf::{Directive, Macro, Mechanism, Spf, SpfPolicyHint};
 
 pub mod arc;
 pub mod common;
@@ -297,6 +297,11 @@ pub struct Resolver {
     pub(crate) cache_ipv4: LruCache<String, Arc<Vec<Ipv4Addr>>>,
     pub(crate) cache_ipv6: LruCache<String, Arc<Vec<Ipv6Addr>>>,
     pub(crate) cache_ptr: LruCache<IpAddr, Arc<Vec<String>>>,
+    pub(crate) spf_max_expansion: usize,
+    pub(crate) spf_timeout: Duration,
+    pub(crate) spf_trusted_forwarders: Vec<Mechanism>,
+    #[cfg(any(test, feature = "test"))]
+    pub(crate) mock_dns: Arc<Mutex<common::resolver::MockDns>>,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -347,6 +352,7 @@ pub struct AuthenticatedMessage<'x> {
     pub received_headers_count: usize,
     pub date_header_present: bool,
     pub message_id_header_present: bool,
+    pub normalize_line_endings: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -403,6 +409,11 @@ pub struct SpfOutput {
     domain: String,
     report: Option<String>,
     explanation: Option<String>,
+    matched: Option<Directive>,
+    ip: Option<IpAddr>,
+    valid_until: Option<Instant>,
+    policy_hint: Option<SpfPolicyHint>,
+    trusted_forwarder: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -438,7 +449,22 @@ pub enum IprevResult {
     None,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+// SPF, DKIM and DMARC results of a message
+pub struct AuthResults<'x> {
+    ip: IpAddr,
+    helo_domain: String,
+    host_domain: String,
+    mail_from: String,
+    header_from: String,
+    spf_ehlo: SpfOutput,
+    spf_mail_from: SpfOutput,
+    dkim: Vec<DkimOutput<'x>>,
+    dmarc: DmarcOutput,
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Version {
     V1,
 }
@@ -454,22 +480,29 @@ pub enum Error {
     UnsupportedVersion,
     UnsupportedAlgorithm,
     UnsupportedCanonicalization,
+    UnsupportedQueryMethod,
     UnsupportedKeyType,
     FailedBodyHashMatch,
     FailedVerification,
     FailedAuidMatch,
+    UnsignedHeaderInstance,
     RevokedPublicKey,
     IncompatibleAlgorithms,
     SignatureExpired,
+    SignatureInFuture,
     DnsError(String),
     DnsRecordNotFound(ResponseCode),
     ArcChainTooLong,
     ArcInvalidInstance(u32),
+    ArcFailedBodyHashMatch(u32),
     ArcInvalidCV,
     ArcHasHeaderTag,
     ArcBrokenChain,
     NotAligned,
     InvalidRecordType,
+    InvalidCharacter,
+    InvalidMacro,
+    MultipleFromDomains,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -488,6 +521,9 @@ impl Display for Error {
             Error::UnsupportedCanonicalization => {
                 write!(f, "Unsupported canonicalization method in DKIM Signature")
             }
+            Error::UnsupportedQueryMethod => {
+                write!(f, "Unsupported query method in DKIM Signature")
+            }
             Error::UnsupportedKeyType => {
                 write!(f, "Unsupported key type in DKIM DNS record")
             }
@@ -501,70 +537,113 @@ impl Display for Error {
             ),
             Error::FailedVerification => write!(f, "Signature verification failed"),
             Error::SignatureExpired => write!(f, "Signature expired"),
+            Error::SignatureInFuture => write!(f, "Signature timestamp is in the future"),
             Error::FailedAuidMatch => write!(f, "AUID does not match domain name"),
+            Error::UnsignedHeaderInstance => {
+                write!(f, "Message contains unsigned instances of a signed header")
+            }
             Error::ArcInvalidInstance(i) => {
                 write!(f, "Invalid 'i={i}' value found in ARC header")
             }
+            Error::ArcFailedBodyHashMatch(i) => {
+                write!(
+                    f,
+                    "Body hash of ARC-Message-Signature 'i={i}' does not match"
+                )
+            }
             Error::ArcInvalidCV => write!(f, "Invalid 'cv=' value found in ARC header"),
             Error::ArcHasHeaderTag => write!(f, "Invalid 'h=' tag present in ARC-Seal"),
             Error::ArcBrokenChain => write!(f, "Broken or missing ARC chain"),
             Error::ArcChainTooLong => write!(f, "Too many ARC headers"),
             Error::InvalidRecordType => write!(f, "Invalid record"),
+            Error::InvalidCharacter => write!(f, "Record contains control characters"),
+            Error::InvalidMacro => write!(f, "Invalid macro"),
             Error::DnsError(err) => write!(f, "DNS resolution error: {err}"),
             Error::DnsRecordNotFound(code) => write!(f, "DNS record not found: {code}"),
             Error::NotAligned => write!(f, "Policy not aligned"),
+            Error::MultipleFromDomains => write!(f, "Multiple RFC5322.From domains"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl SpfResult {
+    /// Returns the RFC 8601 result token, such as `softfail`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpfResult::Pass => "pass",
+            SpfResult::Fail => "fail",
+            SpfResult::SoftFail => "softfail",
+            SpfResult::Neutral => "neutral",
+            SpfResult::TempError => "temperror",
+            SpfResult::PermError => "permerror",
+            SpfResult::None => "none",
+        }
+    }
+}
+
+impl IprevResult {
+    /// Returns the RFC 8601 result token, such as `temperror`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IprevResult::Pass => "pass",
+            IprevResult::Fail(_) => "fail",
+            IprevResult::TempError(_) => "temperror",
+            IprevResult::PermError(_) => "permerror",
+            IprevResult::None => "none",
+        }
+    }
+}
+
+impl DkimResult {
+    /// Returns the RFC 8601 result token, such as `neutral`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DkimResult::Pass => "pass",
+            DkimResult::Fail(_) => "fail",
+            DkimResult::Neutral(_) => "neutral",
+            DkimResult::TempError(_) => "temperror",
+            DkimResult::PermError(_) => "permerror",
+            DkimResult::None => "none",
+        }
+    }
+}
+
+impl DmarcResult {
+    /// Returns the RFC 8601 result token, such as `permerror`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DmarcResult::Pass => "pass",
+            DmarcResult::Fail(_) => "fail",
+            DmarcResult::TempError(_) => "temperror",
+            DmarcResult::PermError(_) => "permerror",
+            DmarcResult::None => "none",
         }
     }
 }
 
 impl Display for SpfResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self {
-            SpfResult::Pass => "Pass",
-            SpfResult::Fail => "Fail",
-            SpfResult::SoftFail => "SoftFail",
-            SpfResult::Neutral => "Neutral",
-            SpfResult::TempError => "TempError",
-            SpfResult::PermError => "PermError",
-            SpfResult::None => "None",
-        })
+        f.write_str(self.as_str())
     }
 }
 
 impl Display for IprevResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            IprevResult::Pass => f.write_str("pass"),
-            IprevResult::Fail(err) => write!(f, "fail; {err}"),
-            IprevResult::TempError(err) => write!(f, "temp error; {err}"),
-            IprevResult::PermError(err) => write!(f, "perm error; {err}"),
-            IprevResult::None => f.write_str("none"),
-        }
+        f.write_str(self.as_str())
     }
 }
 
 impl Display for DkimResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DkimResult::Pass => f.write_str("pass"),
-            DkimResult::Fail(err) => write!(f, "fail; {err}"),
-            DkimResult::Neutral(err) => write!(f, "neutral; {err}"),
-            DkimResult::TempError(err) => write!(f, "temp error; {err}"),
-            DkimResult::PermError(err) => write!(f, "perm error; {err}"),
-            DkimResult::None => f.write_str("none"),
-        }
+        f.write_str(self.as_str())
     }
 }
 
 impl Display for DmarcResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DmarcResult::Pass => f.write_str("pass"),
-            DmarcResult::Fail(err) => write!(f, "fail; {err}"),
-            DmarcResult::TempError(err) => write!(f, "temp error; {err}"),
-            DmarcResult::PermError(err) => write!(f, "perm error; {err}"),
-            DmarcResult::None => f.write_str("none"),
-        }
+        f.write_str(self.as_str())
     }
 }
 
@@ -601,6 +680,11 @@ impl Default for SpfOutput {
             domain: Default::default(),
             report: Default::default(),
             explanation: Default::default(),
+            matched: Default::default(),
+            ip: Default::default(),
+            valid_until: Default::default(),
+            policy_hint: Default::default(),
+            trusted_forwarder: Default::default(),
         }
     }
 }
@@ -632,6 +716,11 @@ impl Clone for Resolver {
             cache_ipv4: Mutex::new(self.cache_ipv4.lock().clone()),
             cache_ipv6: Mutex::new(self.cache_ipv6.lock().clone()),
             cache_ptr: Mutex::new(self.cache_ptr.lock().clone()),
+            spf_max_expansion: self.spf_max_expansion,
+            spf_timeout: self.spf_timeout,
+            spf_trusted_forwarders: self.spf_trusted_forwarders.clone(),
+            #[cfg(any(test, feature = "test"))]
+            mock_dns: self.mock_dns.clone(),
         }
     }
 }
@@ -8,7 +8,12 @@
  * except according to those terms.
  */
 
-use crate::common::parse::{TagParser, TxtRecordParser, V};
+use std::str::FromStr;
+
+use crate::{
+    common::parse::{TagParser, TxtRecordParser, V},
+    Error,
+};
 
 use super::{MtaSts, ReportUri, TlsRpt};
 
@@ -60,6 +65,14 @@ impl TxtRecordParser for MtaSts {
     }
 }
 
+impl FromStr for MtaSts {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MtaSts::parse(s.as_bytes())
+    }
+}
+
 impl TxtRecordParser for TlsRpt {
     #[allow(clippy::while_let_on_iterator)]
     fn parse(record: &[u8]) -> crate::Result<Self> {
@@ -112,6 +125,14 @@ impl TxtRecordParser for TlsRpt {
     }
 }
 
+impl FromStr for TlsRpt {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TlsRpt::parse(s.as_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -165,8 +186,33 @@ mod tests {
                     ],
                 },
             ),
+            (
+                "v=TLSRPTv1; ext=value; rua=mailto:a@example.com,mailto:b@example.com; x=y",
+                TlsRpt {
+                    rua: vec![
+                        ReportUri::Mail("a@example.com".to_string()),
+                        ReportUri::Mail("b@example.com".to_string()),
+                    ],
+                },
+            ),
         ] {
             assert_eq!(TlsRpt::parse(tls_rpt.as_bytes()).unwrap(), expected_tls_rpt);
         }
     }
+
+    #[test]
+    fn tlsrpt_parse_invalid() {
+        for tls_rpt in [
+            "v=TLSRPTv2; rua=mailto:reports@example.com",
+            "v=STSv1; rua=mailto:reports@example.com",
+            "rua=mailto:reports@example.com",
+            "v=TLSRPTv1; ext=value",
+        ] {
+            assert_eq!(
+                TlsRpt::parse(tls_rpt.as_bytes()).unwrap_err(),
+                crate::Error::InvalidRecordType,
+                "{tls_rpt}"
+            );
+        }
+    }
 }
@@ -243,7 +243,11 @@ impl<'x> Feedback<'x> {
 
 #[cfg(test)]
 mod test {
-    use crate::report::{AuthFailureType, Feedback, FeedbackType, IdentityAlignment};
+    use crate::{
+        dkim::Signature,
+        report::{AuthFailureType, Feedback, FeedbackType, IdentityAlignment},
+        DkimOutput, Error,
+    };
 
     #[test]
     fn arf_report_generate() {
@@ -287,4 +291,35 @@ mod test {
 
         assert_eq!(feedback, parsed_feedback);
     }
+
+    #[test]
+    fn arf_dkim_failure_report() {
+        let signature = Signature::parse(
+            concat!(
+                "v=1; a=rsa-sha256; d=example.org; s=default; i=@mail.example.org; ",
+                "h=From; bh=AAAA; b=AAAA"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let output = DkimOutput::neutral(Error::FailedBodyHashMatch).with_signature(&signature);
+        let feedback = Feedback::new(FeedbackType::AuthFailure)
+            .with_dkim_output(&output)
+            .with_original_envelope_id("821-abc-123");
+
+        assert_eq!(feedback.auth_failure(), AuthFailureType::BodyHash);
+        assert_eq!(
+            feedback.to_arf(),
+            concat!(
+                "Version: 1\r\n",
+                "Feedback-Type: auth-failure\r\n",
+                "Auth-Failure: bodyhash\r\n",
+                "DKIM-Domain: example.org\r\n",
+                "DKIM-Identity: @mail.example.org\r\n",
+                "DKIM-Selector: default\r\n",
+                "Original-Envelope-Id: 821-abc-123\r\n",
+                "Reported-Domain: example.org\r\n",
+            )
+        );
+    }
 }
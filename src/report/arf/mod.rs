@@ -10,6 +10,8 @@
 
 use std::{borrow::Cow, net::IpAddr};
 
+use crate::DkimOutput;
+
 use super::{AuthFailureType, DeliveryResult, Feedback, FeedbackType, IdentityAlignment};
 
 pub mod generate;
@@ -263,6 +265,21 @@ impl<'x> Feedback<'x> {
         self
     }
 
+    /// Populates the authentication failure fields of an RFC 6651 DKIM failure
+    /// report from a DKIM verification result.
+    pub fn with_dkim_output(mut self, output: &DkimOutput<'x>) -> Self {
+        self.auth_failure = output.result().into();
+        if let Some(signature) = output.signature {
+            self.reported_domain.push(signature.d.as_str().into());
+            self.dkim_domain = Some(signature.d.as_str().into());
+            self.dkim_selector = Some(signature.s.as_str().into());
+            if !signature.i.is_empty() {
+                self.dkim_identity = Some(signature.i.as_str().into());
+            }
+        }
+        self
+    }
+
     pub fn into_owned<'y>(self) -> Feedback<'y> {
         Feedback {
             feedback_type: self.feedback_type,
@@ -136,10 +136,22 @@ impl Report {
     }
 
     pub fn parse_xml(report: &[u8]) -> Result<Self, String> {
+        let mut record = Vec::new();
+        let mut report = Report::parse_xml_stream(report, |r| record.push(r))?;
+        report.record = record;
+        Ok(report)
+    }
+
+    /// Parses an aggregate report from a reader, passing each `<record>` row
+    /// to `on_record` as soon as it is read so that large reports can be
+    /// processed with bounded memory. The returned report holds no records.
+    pub fn parse_xml_stream<R: BufRead>(
+        report: R,
+        mut on_record: impl FnMut(Record),
+    ) -> Result<Self, String> {
         let mut version: f32 = 0.0;
         let mut report_metadata = None;
         let mut policy_published = None;
-        let mut record = Vec::new();
         let mut extensions = Vec::new();
 
         let mut reader = Reader::from_reader(report);
@@ -163,7 +175,7 @@ impl Report {
                     policy_published = PolicyPublished::parse(&mut reader, &mut buf)?.into();
                 }
                 b"record" if found_feedback => {
-                    record.push(Record::parse(&mut reader, &mut buf)?);
+                    on_record(Record::parse(&mut reader, &mut buf)?);
                 }
                 b"extensions" if found_feedback => {
                     Extension::parse(&mut reader, &mut buf, &mut extensions)?;
@@ -184,7 +196,7 @@ impl Report {
             version,
             report_metadata: report_metadata.ok_or("Missing feedback/report_metadata tag.")?,
             policy_published: policy_published.ok_or("Missing feedback/policy_published tag.")?,
-            record,
+            record: Vec::new(),
             extensions,
         })
     }
@@ -768,7 +780,7 @@ impl<R: BufRead> ReaderHelper for Reader<R> {
 
 #[cfg(test)]
 mod test {
-    use std::{fs, path::PathBuf};
+    use std::{fs, io::BufReader, path::PathBuf};
 
     use crate::report::Report;
 
@@ -831,4 +843,47 @@ mod test {
             .unwrap();*/
         }
     }
+
+    #[test]
+    fn dmarc_report_stream_parse() {
+        let mut xml = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<feedback>\n",
+            "<report_metadata><org_name>Sample Reporter</org_name>",
+            "<report_id>abc</report_id></report_metadata>\n",
+            "<policy_published><domain>example.com</domain><p>reject</p></policy_published>\n",
+        )
+        .to_string();
+        for n in 0..10_000 {
+            xml.push_str(&format!(
+                concat!(
+                    "<record><row><source_ip>10.0.{}.{}</source_ip><count>{}</count></row>",
+                    "<identifiers><header_from>example.com</header_from></identifiers></record>\n"
+                ),
+                n / 256,
+                n % 256,
+                n % 3 + 1
+            ));
+        }
+        xml.push_str("</feedback>\n");
+
+        let mut rows = 0;
+        let mut messages = 0;
+        let report = Report::parse_xml_stream(BufReader::new(xml.as_bytes()), |record| {
+            rows += 1;
+            messages += record.count();
+        })
+        .unwrap();
+        assert_eq!(rows, 10_000);
+        assert_eq!(messages, 19_999);
+        assert!(report.records().is_empty());
+        assert_eq!(report.domain(), "example.com");
+        assert_eq!(report.report_id(), "abc");
+
+        let report = Report::parse_xml(xml.as_bytes()).unwrap();
+        assert_eq!(report.records().len(), 10_000);
+        assert_eq!(
+            report.records()[9_999].source_ip(),
+            Some("10.0.39.15".parse().unwrap())
+        );
+    }
 }
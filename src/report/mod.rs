@@ -298,7 +298,9 @@ impl From<&crate::DkimResult> for AuthFailureType {
             | crate::DkimResult::Fail(err)
             | crate::DkimResult::PermError(err)
             | crate::DkimResult::TempError(err) => match err {
-                crate::Error::FailedBodyHashMatch => AuthFailureType::BodyHash,
+                crate::Error::FailedBodyHashMatch | crate::Error::ArcFailedBodyHashMatch(_) => {
+                    AuthFailureType::BodyHash
+                }
                 crate::Error::RevokedPublicKey => AuthFailureType::Revoked,
                 _ => AuthFailureType::Signature,
             },
@@ -0,0 +1,521 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::sync::Arc;
+
+use crate::{Error, Resolver};
+
+use super::{Directive, Macro, Mechanism, Qualifier, Spf, Variable, Variables, Warning};
+
+const MAX_LOOKUPS: usize = 10;
+
+impl Spf {
+    /// Resolves the `include` and `redirect` terms with static targets into the
+    /// `ip4` and `ip6` mechanisms they authorize. Terms that cannot be flattened,
+    /// such as those whose target depends on macro expansion, are kept as they
+    /// are and reported as warnings.
+    pub async fn flatten(&self, resolver: &Resolver) -> crate::Result<(Spf, Vec<Warning>)> {
+        let mut flattened = Spf {
+            version: self.version.clone(),
+            directives: Vec::with_capacity(self.directives.len()),
+            exp: self.exp.clone(),
+            redirect: None,
+            ra: self.ra.clone(),
+            rp: self.rp,
+            rr: self.rr,
+            modifiers: self.modifiers.clone(),
+        };
+        let mut warnings = Vec::new();
+        let mut lookups = 0;
+        let mut record = Arc::new(self.clone());
+        let mut domain: Option<String> = None;
+
+        'outer: loop {
+            for directive in &record.directives {
+                match &directive.mechanism {
+                    Mechanism::Include { macro_string } => {
+                        match static_target(macro_string, domain.as_deref()) {
+                            Some(target) => match flatten_include(
+                                resolver,
+                                &target,
+                                &directive.qualifier,
+                                &mut lookups,
+                            )
+                            .await?
+                            {
+                                Ok((directives, is_terminal)) => {
+                                    flattened.directives.extend(directives);
+                                    if is_terminal {
+                                        break 'outer;
+                                    }
+                                }
+                                Err(warning) => {
+                                    flattened.directives.push(Directive::new(
+                                        directive.qualifier,
+                                        Mechanism::Include {
+                                            macro_string: Macro::Literal(target.into_bytes()),
+                                        },
+                                    ));
+                                    warnings.push(warning);
+                                }
+                            },
+                            None => {
+                                flattened.directives.push(
+                                    relocate_directive(directive, domain.as_deref()).unwrap(),
+                                );
+                                warnings.push(Warning::UnresolvedMacro(macro_string.clone()));
+                            }
+                        }
+                    }
+                    Mechanism::All => {
+                        // Any redirect is ignored when there is an "all" mechanism
+                        flattened.directives.push(directive.clone());
+                        break 'outer;
+                    }
+                    _ => {
+                        flattened
+                            .directives
+                            .push(relocate_directive(directive, domain.as_deref()).unwrap());
+                    }
+                }
+            }
+
+            if let Some(macro_string) = &record.redirect {
+                match static_target(macro_string, domain.as_deref()) {
+                    Some(target) if lookups < MAX_LOOKUPS => {
+                        lookups += 1;
+                        let redirect = match lookup_record(resolver, &target).await? {
+                            Some(redirect) => redirect,
+                            None => {
+                                flattened.redirect =
+                                    Macro::Literal(target.as_bytes().to_vec()).into();
+                                warnings.push(Warning::Unflattenable(target));
+                                break;
+                            }
+                        };
+                        let exp = match &redirect.exp {
+                            Some(exp) => relocate(exp, Some(&target)).map(Some),
+                            None => Some(None),
+                        };
+
+                        // The terms of the redirected record are moved to this record,
+                        // which requires their targets to be independent of the domain.
+                        match exp {
+                            Some(exp)
+                                if redirect
+                                    .directives
+                                    .iter()
+                                    .all(|d| relocate_directive(d, Some(&target)).is_some())
+                                    && redirect
+                                        .redirect
+                                        .as_ref()
+                                        .is_none_or(|r| relocate(r, Some(&target)).is_some()) =>
+                            {
+                                flattened.exp = exp;
+                                record = redirect;
+                                domain = Some(target);
+                                continue;
+                            }
+                            _ => {
+                                flattened.redirect =
+                                    Macro::Literal(target.as_bytes().to_vec()).into();
+                                warnings.push(Warning::Unflattenable(target));
+                            }
+                        }
+                    }
+                    Some(target) => {
+                        flattened.redirect = Macro::Literal(target.into_bytes()).into();
+                        warnings.push(Warning::TooManyLookups);
+                    }
+                    None => {
+                        flattened.redirect = relocate(macro_string, domain.as_deref());
+                        warnings.push(Warning::UnresolvedMacro(macro_string.clone()));
+                    }
+                }
+            }
+
+            break;
+        }
+
+        Ok((flattened, warnings))
+    }
+}
+
+// Flattens an included record into the IP mechanisms it authorizes, returning
+// whether the record always matches. Included records are flattened only when
+// all their terms can be expressed as IP mechanisms with the include's qualifier.
+async fn flatten_include(
+    resolver: &Resolver,
+    include_domain: &str,
+    qualifier: &Qualifier,
+    lookups: &mut usize,
+) -> crate::Result<Result<(Vec<Directive>, bool), Warning>> {
+    let mut directives = Vec::new();
+    let mut stack = Vec::new();
+    let mut domain = include_domain.to_string();
+    let mut pos = 0;
+
+    if *lookups >= MAX_LOOKUPS {
+        return Ok(Err(Warning::TooManyLookups));
+    }
+    *lookups += 1;
+    let mut record = match lookup_record(resolver, &domain).await? {
+        Some(record) => record,
+        None => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+    };
+
+    loop {
+        let directive = if let Some(directive) = record.directives.get(pos) {
+            directive
+        } else {
+            // Follow the redirect of the included record
+            if let Some(macro_string) = &record.redirect {
+                match static_target(macro_string, Some(&domain)) {
+                    Some(target) if *lookups < MAX_LOOKUPS => {
+                        *lookups += 1;
+                        record = match lookup_record(resolver, &target).await? {
+                            Some(record) => record,
+                            None => {
+                                return Ok(Err(Warning::Unflattenable(include_domain.to_string())))
+                            }
+                        };
+                        domain = target;
+                        pos = 0;
+                        continue;
+                    }
+                    Some(_) => return Ok(Err(Warning::TooManyLookups)),
+                    None => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+                }
+            }
+
+            // The record did not match, resume the including record
+            if let Some((prev_record, prev_pos, prev_domain)) = stack.pop() {
+                record = prev_record;
+                pos = prev_pos;
+                domain = prev_domain;
+                continue;
+            } else {
+                return Ok(Ok((directives, false)));
+            }
+        };
+        pos += 1;
+
+        match (&directive.qualifier, &directive.mechanism) {
+            (Qualifier::Pass, Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. }) => {
+                directives.push(Directive::new(*qualifier, directive.mechanism.clone()));
+            }
+            (Qualifier::Pass, Mechanism::All) => {
+                directives.push(Directive::new(*qualifier, Mechanism::All));
+                return Ok(Ok((directives, true)));
+            }
+            (_, Mechanism::All) => {
+                // The record ends without a match and its redirect is ignored
+                if let Some((prev_record, prev_pos, prev_domain)) = stack.pop() {
+                    record = prev_record;
+                    pos = prev_pos;
+                    domain = prev_domain;
+                } else {
+                    return Ok(Ok((directives, false)));
+                }
+            }
+            (
+                Qualifier::Pass,
+                Mechanism::A {
+                    macro_string,
+                    ip4_mask,
+                    ip6_mask,
+                }
+                | Mechanism::Mx {
+                    macro_string,
+                    ip4_mask,
+                    ip6_mask,
+                },
+            ) => {
+                let target = match static_target(macro_string, Some(&domain)) {
+                    Some(target) if *lookups < MAX_LOOKUPS => target,
+                    Some(_) => return Ok(Err(Warning::TooManyLookups)),
+                    None => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+                };
+                *lookups += 1;
+
+                let hosts = if matches!(directive.mechanism, Mechanism::Mx { .. }) {
+                    match resolver.mx_lookup(target.as_str()).await {
+                        Ok(mxs) => mxs
+                            .iter()
+                            .flat_map(|mx| mx.exchanges.iter().cloned())
+                            .collect(),
+                        Err(Error::DnsRecordNotFound(_)) => vec![],
+                        Err(err) => return Err(err),
+                    }
+                } else {
+                    vec![target]
+                };
+
+                for host in hosts {
+                    match resolver.ipv4_lookup(host.as_str()).await {
+                        Ok(addrs) => {
+                            directives.extend(addrs.iter().map(|addr| {
+                                Directive::new(
+                                    *qualifier,
+                                    Mechanism::Ip4 {
+                                        addr: *addr,
+                                        mask: *ip4_mask,
+                                    },
+                                )
+                            }));
+                        }
+                        Err(Error::DnsRecordNotFound(_)) => (),
+                        Err(err) => return Err(err),
+                    }
+                    match resolver.ipv6_lookup(host.as_str()).await {
+                        Ok(addrs) => {
+                            directives.extend(addrs.iter().map(|addr| {
+                                Directive::new(
+                                    *qualifier,
+                                    Mechanism::Ip6 {
+                                        addr: *addr,
+                                        mask: *ip6_mask,
+                                    },
+                                )
+                            }));
+                        }
+                        Err(Error::DnsRecordNotFound(_)) => (),
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            (Qualifier::Pass, Mechanism::Include { macro_string }) => {
+                let target = match static_target(macro_string, Some(&domain)) {
+                    Some(target) if *lookups < MAX_LOOKUPS => target,
+                    Some(_) => return Ok(Err(Warning::TooManyLookups)),
+                    None => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+                };
+                *lookups += 1;
+                let included = match lookup_record(resolver, &target).await? {
+                    Some(included) => included,
+                    None => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+                };
+                stack.push((
+                    std::mem::replace(&mut record, included),
+                    pos,
+                    std::mem::replace(&mut domain, target),
+                ));
+                pos = 0;
+            }
+            _ => return Ok(Err(Warning::Unflattenable(include_domain.to_string()))),
+        }
+    }
+}
+
+// Looks up the SPF record of an include or redirect target. Targets without a
+// valid record can't be flattened, so they are kept as they are.
+async fn lookup_record(resolver: &Resolver, name: &str) -> crate::Result<Option<Arc<Spf>>> {
+    match resolver.txt_lookup::<Spf>(name).await {
+        Ok(record) => Ok(Some(record)),
+        Err(Error::DnsRecordNotFound(_) | Error::InvalidRecordType | Error::ParseError) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// Expands a macro that only depends on the current domain.
+fn static_target(macro_string: &Macro, domain: Option<&str>) -> Option<String> {
+    let is_static = match macro_string {
+        Macro::Literal(_) => true,
+        Macro::Variable { letter, .. } => *letter == Variable::Domain,
+        Macro::List(list) => list.iter().all(|item| match item {
+            Macro::Variable { letter, .. } => *letter == Variable::Domain,
+            _ => true,
+        }),
+        Macro::None => true,
+    };
+
+    match (macro_string, domain) {
+        (Macro::Literal(literal), _) => String::from_utf8(literal.clone()).ok(),
+        (_, Some(domain)) if is_static => {
+            let mut vars = Variables::default();
+            vars.set_domain(domain.as_bytes());
+            let target = macro_string.eval(&vars, domain, false).into_owned();
+            Some(target)
+        }
+        _ => None,
+    }
+}
+
+// Rewrites a macro to be evaluated in a record published at a different domain.
+fn relocate(macro_string: &Macro, domain: Option<&str>) -> Option<Macro> {
+    if domain.is_none() {
+        Some(macro_string.clone())
+    } else if let Some(target) = static_target(macro_string, domain) {
+        Some(Macro::Literal(target.into_bytes()))
+    } else if macro_string.needs_domain() {
+        None
+    } else {
+        Some(macro_string.clone())
+    }
+}
+
+fn relocate_directive(directive: &Directive, domain: Option<&str>) -> Option<Directive> {
+    let mechanism = match &directive.mechanism {
+        Mechanism::Include { macro_string } => Mechanism::Include {
+            macro_string: relocate(macro_string, domain)?,
+        },
+        Mechanism::A {
+            macro_string,
+            ip4_mask,
+            ip6_mask,
+        } => Mechanism::A {
+            macro_string: relocate(macro_string, domain)?,
+            ip4_mask: *ip4_mask,
+            ip6_mask: *ip6_mask,
+        },
+        Mechanism::Mx {
+            macro_string,
+            ip4_mask,
+            ip6_mask,
+        } => Mechanism::Mx {
+            macro_string: relocate(macro_string, domain)?,
+            ip4_mask: *ip4_mask,
+            ip6_mask: *ip6_mask,
+        },
+        Mechanism::Ptr { macro_string } => Mechanism::Ptr {
+            macro_string: relocate(macro_string, domain)?,
+        },
+        Mechanism::Exists { macro_string } => Mechanism::Exists {
+            macro_string: relocate(macro_string, domain)?,
+        },
+        Mechanism::All | Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. } => {
+            directive.mechanism.clone()
+        }
+    };
+
+    Some(Directive::new(directive.qualifier, mechanism))
+}
+
+impl Macro {
+    fn needs_domain(&self) -> bool {
+        match self {
+            Macro::Variable { letter, .. } => *letter == Variable::Domain,
+            Macro::List(list) => list.iter().any(|m| m.needs_domain()),
+            Macro::Literal(_) => false,
+            Macro::None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        common::parse::TxtRecordParser,
+        spf::{Macro, Spf, Warning},
+        Resolver,
+    };
+
+    #[tokio::test]
+    async fn spf_flatten() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (name, record) in [
+            (
+                "_spf.example.org.",
+                "v=spf1 ip4:192.0.2.0/24 include:_spf2.example.org ~all",
+            ),
+            (
+                "_spf2.example.org.",
+                "v=spf1 ip6:2001:db8::/32 ip4:198.51.100.1 a/28 -all",
+            ),
+            ("_ptr.example.org.", "v=spf1 ip4:192.0.2.1 ptr -all"),
+            ("_redirect.example.org.", "v=spf1 a:%{d} ~all"),
+            (
+                "_broken.example.org.",
+                "v=spf1 include:_parse_error.example.org",
+            ),
+        ] {
+            resolver.txt_add(name, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+        }
+        for name in ["_spf2.example.org.", "_redirect.example.org."] {
+            resolver.ipv4_add(name, vec![Ipv4Addr::new(203, 0, 113, 5)], valid_until);
+        }
+
+        for (record, expected, expected_warnings) in [
+            (
+                "v=spf1 include:_spf.example.org ip4:10.0.0.1 -all",
+                concat!(
+                    "v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 ip4:198.51.100.1 ",
+                    "ip4:203.0.113.5/28 ip4:10.0.0.1 -all"
+                ),
+                vec![],
+            ),
+            (
+                "v=spf1 ~include:_spf2.example.org redirect=_redirect.example.org",
+                concat!(
+                    "v=spf1 ~ip6:2001:db8::/32 ~ip4:198.51.100.1 ~ip4:203.0.113.5/28 ",
+                    "a:_redirect.example.org ~all"
+                ),
+                vec![],
+            ),
+            (
+                "v=spf1 include:_ptr.example.org include:%{l}.example.org -all",
+                "v=spf1 include:_ptr.example.org include:%{l}.example.org -all",
+                vec![
+                    Warning::Unflattenable("_ptr.example.org".to_string()),
+                    Warning::UnresolvedMacro(
+                        Spf::parse(b"v=spf1 include:%{l}.example.org")
+                            .unwrap()
+                            .lookup_terms()
+                            .next()
+                            .unwrap()
+                            .macro_string()
+                            .clone(),
+                    ),
+                ],
+            ),
+            (
+                "v=spf1 include:_broken.example.org include:_missing.example.org -all",
+                "v=spf1 include:_broken.example.org include:_missing.example.org -all",
+                vec![
+                    Warning::Unflattenable("_broken.example.org".to_string()),
+                    Warning::Unflattenable("_missing.example.org".to_string()),
+                ],
+            ),
+            (
+                "v=spf1 ip4:10.0.0.1 redirect=_missing.example.org",
+                "v=spf1 ip4:10.0.0.1 redirect=_missing.example.org",
+                vec![Warning::Unflattenable("_missing.example.org".to_string())],
+            ),
+        ] {
+            let (flattened, warnings) = Spf::parse(record.as_bytes())
+                .unwrap()
+                .flatten(&resolver)
+                .await
+                .unwrap();
+            assert_eq!(
+                flattened,
+                Spf::parse(expected.as_bytes()).unwrap(),
+                "{record}"
+            );
+            assert_eq!(warnings, expected_warnings, "{record}");
+        }
+
+        // Redirect targets depending on macros are left as they are
+        let (flattened, warnings) =
+            Spf::parse(b"v=spf1 ip4:10.0.0.1 redirect=%{o}._spf.example.org")
+                .unwrap()
+                .flatten(&resolver)
+                .await
+                .unwrap();
+        assert!(matches!(flattened.redirect, Some(Macro::List(_))));
+        assert!(matches!(&warnings[..], [Warning::UnresolvedMacro(_)]));
+    }
+}
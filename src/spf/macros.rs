@@ -10,7 +10,7 @@
 
 use std::{borrow::Cow, net::IpAddr, time::SystemTime};
 
-use super::{Macro, Variable, Variables};
+use super::{Macro, SpfContext, Variable, Variables};
 
 impl Macro {
     pub fn eval<'z, 'x: 'z>(
@@ -19,7 +19,20 @@ impl Macro {
         default: &'x str,
         fqdn: bool,
     ) -> Cow<'z, str> {
-        match self {
+        self.eval_with_limit(vars, default, fqdn, usize::MAX)
+            .unwrap_or_default()
+    }
+
+    /// Expands the macro, returning `None` if the expansion is longer than
+    /// `max_len` bytes, not counting the trailing dot of a FQDN.
+    pub fn eval_with_limit<'z, 'x: 'z>(
+        &'z self,
+        vars: &'x Variables<'x>,
+        default: &'x str,
+        fqdn: bool,
+        max_len: usize,
+    ) -> Option<Cow<'z, str>> {
+        let result: Cow<'z, str> = match self {
             Macro::Literal(literal) => std::str::from_utf8(literal).unwrap_or_default().into(),
             Macro::Variable {
                 letter,
@@ -34,6 +47,11 @@ impl Macro {
             Macro::List(list) => {
                 let mut result = Vec::with_capacity(32);
                 for item in list {
+                    // Stop expanding as soon as the limit is exceeded
+                    if result.len() > max_len.saturating_add(1) {
+                        return None;
+                    }
+
                     match item {
                         Macro::Literal(literal) => {
                             result.extend_from_slice(literal);
@@ -66,6 +84,44 @@ impl Macro {
                 String::from_utf8(result).unwrap_or_default().into()
             }
             Macro::None => default.into(),
+        };
+
+        if result.strip_suffix('.').unwrap_or(&result).len() <= max_len {
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    /// Expands a domain-spec, returning `None` if the expansion is empty or
+    /// longer than `max_len` bytes.
+    pub(crate) fn eval_domain<'z, 'x: 'z>(
+        &'z self,
+        vars: &'x Variables<'x>,
+        default: &'x str,
+        max_len: usize,
+    ) -> Option<Cow<'z, str>> {
+        self.eval_with_limit(vars, default, true, max_len)
+            .filter(|name| !name.trim_end_matches('.').is_empty())
+    }
+
+    /// Returns `true` if the macro expands to the same name regardless of
+    /// the evaluation variables. An omitted domain-spec ([`Macro::None`])
+    /// is not static as it evaluates to the current domain.
+    pub fn is_static(&self) -> bool {
+        match self {
+            Macro::Literal(_) => true,
+            Macro::List(list) => list.iter().all(|m| matches!(m, Macro::Literal(_))),
+            Macro::Variable { .. } | Macro::None => false,
+        }
+    }
+
+    /// Returns the domain-spec if it is a literal that does not need to be
+    /// expanded.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self {
+            Macro::Literal(literal) => std::str::from_utf8(literal).ok(),
+            _ => None,
         }
     }
 
@@ -78,16 +134,60 @@ impl Macro {
     }
 }
 
+impl SpfContext {
+    /// Creates the context of a check for `sender`, splitting it at the last
+    /// `@`. A sender without a local-part gets `postmaster` as local-part, and
+    /// an empty sender, or one without a domain, becomes `postmaster@helo_domain`.
+    pub fn from_sender(sender: &str, ip: IpAddr, helo_domain: &str, domain: &str) -> Self {
+        let sender = match sender.rsplit_once('@') {
+            Some((local_part, sender_domain)) if !sender_domain.is_empty() => {
+                if !local_part.is_empty() {
+                    sender.to_string()
+                } else {
+                    format!("postmaster@{sender_domain}")
+                }
+            }
+            _ => format!("postmaster@{helo_domain}"),
+        };
+
+        SpfContext {
+            ip,
+            helo_domain: helo_domain.to_string(),
+            domain: domain.to_string(),
+            sender,
+        }
+    }
+
+    /// Returns the local-part of the sender, which `%{l}` expands to.
+    pub fn local_part(&self) -> &str {
+        self.sender.rsplit_once('@').map_or("", |(l, _)| l)
+    }
+
+    /// Returns the domain of the sender, which `%{o}` expands to.
+    pub fn sender_domain(&self) -> &str {
+        self.sender.rsplit_once('@').map_or("", |(_, d)| d)
+    }
+
+    /// Returns the macro variables of the context.
+    pub fn variables(&self) -> Variables<'_> {
+        let mut vars = Variables::new();
+        vars.set_ip(&self.ip);
+        vars.set_sender(self.sender.as_bytes());
+        vars.set_domain(self.domain.as_bytes());
+        vars.set_helo_domain(self.helo_domain.as_bytes());
+        vars
+    }
+}
+
 impl<'x> Variables<'x> {
     pub fn new() -> Self {
         let mut vars = Variables::default();
-        vars.vars[Variable::CurrentTime as usize] = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0)
-            .to_string()
-            .into_bytes()
-            .into();
+        vars.set_current_time(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        );
         vars
     }
 
@@ -118,20 +218,18 @@ impl<'x> Variables<'x> {
 
     pub fn set_sender(&mut self, value: impl Into<Cow<'x, [u8]>>) {
         let value = value.into();
-        for (pos, ch) in value.as_ref().iter().enumerate() {
-            if ch == &b'@' {
-                if pos > 0 {
-                    self.vars[Variable::SenderLocalPart as usize] = match &value {
-                        Cow::Borrowed(value) => (&value[..pos]).into(),
-                        Cow::Owned(value) => value[..pos].to_vec().into(),
-                    };
-                }
-                self.vars[Variable::SenderDomainPart as usize] = match &value {
-                    Cow::Borrowed(value) => (value.get(pos + 1..).unwrap_or_default()).into(),
-                    Cow::Owned(value) => (value.get(pos + 1..).unwrap_or_default()).to_vec().into(),
+        // The local-part may itself contain a quoted "@"
+        if let Some(pos) = value.iter().rposition(|&ch| ch == b'@') {
+            if pos > 0 {
+                self.vars[Variable::SenderLocalPart as usize] = match &value {
+                    Cow::Borrowed(value) => (&value[..pos]).into(),
+                    Cow::Owned(value) => value[..pos].to_vec().into(),
                 };
-                break;
             }
+            self.vars[Variable::SenderDomainPart as usize] = match &value {
+                Cow::Borrowed(value) => (value.get(pos + 1..).unwrap_or_default()).into(),
+                Cow::Owned(value) => (value.get(pos + 1..).unwrap_or_default()).to_vec().into(),
+            };
         }
 
         self.vars[Variable::Sender as usize] = value;
@@ -149,6 +247,11 @@ impl<'x> Variables<'x> {
         self.vars[Variable::ValidatedDomain as usize] = value.into();
     }
 
+    /// Sets the UNIX time the `%{t}` macro expands to.
+    pub fn set_current_time(&mut self, now: u64) {
+        self.vars[Variable::CurrentTime as usize] = now.to_string().into_bytes().into();
+    }
+
     pub fn set_domain(&mut self, value: impl Into<Cow<'x, [u8]>>) {
         self.vars[Variable::Domain as usize] = value.into();
     }
@@ -168,6 +271,9 @@ impl<'x> Variables<'x> {
         {
             return var.into();
         }
+
+        // Trailing dots do not delimit an empty label
+        let var = var.strip_suffix(b".").unwrap_or(var);
         let mut parts = Vec::new();
         let mut parts_len = 0;
         let mut start_pos = 0;
@@ -226,7 +332,10 @@ fn add_part(result: &mut Vec<u8>, part: &[u8], pos: usize, escape: bool) {
 mod test {
     use std::net::IpAddr;
 
-    use crate::spf::{parse::SPFParser, Variables};
+    use crate::{
+        common::parse::TxtRecordParser,
+        spf::{parse::SPFParser, Macro, Mechanism, Spf, SpfContext, Variables},
+    };
 
     #[test]
     fn expand_macro() {
@@ -306,5 +415,208 @@ mod test {
             let (m, _) = macro_string.as_bytes().iter().macro_string(true).unwrap();
             assert_eq!(m.eval(&vars, "", false), expansion, "{macro_string:?}");
         }
+
+        let mut vars = Variables::new();
+        vars.set_sender("user+tag/100%~_x@ex.com".as_bytes());
+
+        for (macro_string, expansion) in [
+            ("%{S}", "user%2btag%2f100%25~_x%40ex.com"),
+            ("%{L}", "user%2btag%2f100%25~_x"),
+            ("%{O}", "ex.com"),
+            ("%{Sr}", "com.user%2btag%2f100%25~_x%40ex"),
+            ("%{s}", "user+tag/100%~_x@ex.com"),
+        ] {
+            let (m, _) = macro_string.as_bytes().iter().macro_string(true).unwrap();
+            assert_eq!(m.eval(&vars, "", false), expansion, "{macro_string:?}");
+        }
+
+        let mut vars = Variables::new();
+        vars.set_domain("mail.email.example.com.".as_bytes());
+
+        for (macro_string, expansion) in [
+            ("%{d1}", "com"),
+            ("%{d2}", "example.com"),
+            ("%{d2r}", "email.mail"),
+            ("%{dr}", "com.example.email.mail"),
+            ("%{d2}._spf.example.org", "example.com._spf.example.org"),
+        ] {
+            let (m, _) = macro_string.as_bytes().iter().macro_string(false).unwrap();
+            assert_eq!(m.eval(&vars, "", false), expansion, "{macro_string:?}");
+        }
+
+        let mut vars = Variables::new();
+        vars.set_sender("user@a=b_c/d,e.f+g".as_bytes());
+
+        for (macro_string, expansion) in [
+            ("%{o,=_/}", "a.b.c.d.e.f+g"),
+            ("%{o=_/}", "a.b.c.d,e.f+g"),
+            ("%{or,=_/}", "e.f+g.d.c.b.a"),
+            ("%{o2,=_/}", "d.e.f+g"),
+            ("%{o2r=_/}", "b.a"),
+            ("%{o+-.,/_=}", "a.b.c.d.e.f.g"),
+            ("%{or+-.,/_=}", "g.f.e.d.c.b.a"),
+            ("%{or}", "f+g.a=b_c/d,e"),
+            ("%{o3r.+}", "g.f.a=b_c/d,e"),
+        ] {
+            let (m, _) = macro_string.as_bytes().iter().macro_string(false).unwrap();
+            assert_eq!(m.eval(&vars, "", false), expansion, "{macro_string:?}");
+        }
+    }
+
+    #[test]
+    fn expand_ip() {
+        for (ip, macro_string, expansion) in [
+            ("192.0.2.1", "%{i}", "192.0.2.1"),
+            ("192.0.2.1", "%{ir}", "1.2.0.192"),
+            (
+                "2001:db8::1",
+                "%{i}",
+                "2.0.0.1.0.d.b.8.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.1",
+            ),
+            (
+                "2001:db8::1",
+                "%{ir}",
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2",
+            ),
+            ("2001:db8::1", "%{i4}", "0.0.0.1"),
+            ("2001:db8::1", "%{c}", "2001:db8::1"),
+        ] {
+            let mut vars = Variables::new();
+            vars.set_ip(&ip.parse::<IpAddr>().unwrap());
+            let (m, _) = macro_string.as_bytes().iter().macro_string(true).unwrap();
+            assert_eq!(m.eval(&vars, "", false), expansion, "{ip} {macro_string}");
+        }
+    }
+
+    #[test]
+    fn expand_target_domain() {
+        let mut vars = Variables::new();
+        vars.set_sender("sender@example.org".as_bytes());
+        vars.set_ip(&"192.0.2.3".parse::<IpAddr>().unwrap());
+        vars.set_domain("example.org".as_bytes());
+
+        let spf = Spf::parse(
+            b"v=spf1 exists:%{ir}.x.com a mx:mx.%{d} ip4:192.0.2.0/24 -all redirect=_spf.%{d}",
+        )
+        .unwrap();
+        assert_eq!(
+            spf.directives
+                .iter()
+                .map(|d| d.mechanism.target_domain(&vars, "example.org"))
+                .collect::<Vec<_>>(),
+            vec![
+                Some("3.2.0.192.x.com".to_string()),
+                Some("example.org".to_string()),
+                Some("mx.example.org".to_string()),
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            spf.lookup_terms()
+                .map(|t| t.target_domain(&vars, "example.org"))
+                .collect::<Vec<_>>(),
+            vec![
+                "3.2.0.192.x.com",
+                "example.org",
+                "mx.example.org",
+                "_spf.example.org"
+            ]
+        );
+    }
+
+    #[test]
+    fn static_targets() {
+        let spf = Spf::parse(
+            b"v=spf1 include:_spf.example.org exists:%{i}._spf.%{d} a -all redirect=_spf.%{d}",
+        )
+        .unwrap();
+        let targets = spf
+            .directives
+            .iter()
+            .filter_map(|d| match &d.mechanism {
+                Mechanism::Include { macro_string }
+                | Mechanism::Exists { macro_string }
+                | Mechanism::A { macro_string, .. } => Some(macro_string),
+                _ => None,
+            })
+            .chain(spf.redirect.as_ref())
+            .map(|m| (m.is_static(), m.as_literal()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                (true, Some("_spf.example.org")),
+                (false, None),
+                (false, None),
+                (false, None)
+            ]
+        );
+
+        let list = Macro::List(vec![
+            Macro::Literal(b"_spf.".to_vec()),
+            Macro::Literal(b"example.org".to_vec()),
+        ]);
+        assert!(list.is_static());
+        assert_eq!(list.as_literal(), None);
+    }
+
+    #[test]
+    fn spf_context_from_sender() {
+        let ip = "192.0.2.3".parse::<IpAddr>().unwrap();
+        let macro_string = "%{s}%_%{l}%_%{o}%_%{d}%_%{h}"
+            .as_bytes()
+            .iter()
+            .macro_string(false)
+            .unwrap()
+            .0;
+
+        for (sender, expected_sender, expected) in [
+            (
+                "strong-bad@email.example.com",
+                "strong-bad@email.example.com",
+                concat!(
+                    "strong-bad@email.example.com strong-bad email.example.com ",
+                    "example.com mx.example.org"
+                ),
+            ),
+            (
+                "\"strong@bad\"@email.example.com",
+                "\"strong@bad\"@email.example.com",
+                concat!(
+                    "\"strong@bad\"@email.example.com \"strong@bad\" email.example.com ",
+                    "example.com mx.example.org"
+                ),
+            ),
+            (
+                "@email.example.com",
+                "postmaster@email.example.com",
+                concat!(
+                    "postmaster@email.example.com postmaster email.example.com ",
+                    "example.com mx.example.org"
+                ),
+            ),
+            (
+                "",
+                "postmaster@mx.example.org",
+                concat!(
+                    "postmaster@mx.example.org postmaster mx.example.org ",
+                    "example.com mx.example.org"
+                ),
+            ),
+        ] {
+            let context = SpfContext::from_sender(sender, ip, "mx.example.org", "example.com");
+            assert_eq!(context.sender, expected_sender, "{sender:?}");
+            assert_eq!(
+                format!("{}@{}", context.local_part(), context.sender_domain()),
+                expected_sender,
+                "{sender:?}"
+            );
+            assert_eq!(
+                macro_string.eval(&context.variables(), "", false),
+                expected,
+                "{sender:?}"
+            );
+        }
     }
 }
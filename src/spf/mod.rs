@@ -8,17 +8,26 @@
  * except according to those terms.
  */
 
+pub mod flatten;
 pub mod macros;
 pub mod parse;
+#[cfg(feature = "serde")]
+pub(crate) mod serialize;
+pub mod validate;
 pub mod verify;
 
 use std::{
     borrow::Cow,
-    net::{Ipv4Addr, Ipv6Addr},
+    collections::HashSet,
+    fmt::{Display, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
 };
 
 use crate::{is_within_pct, SpfOutput, SpfResult, Version};
 
+use self::verify::IpMask;
+
 /*
       "+" pass
       "-" fail
@@ -26,7 +35,8 @@ use crate::{is_within_pct, SpfOutput, SpfResult, Version};
       "?" neutral
 */
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Qualifier {
     Pass,
     Fail,
@@ -38,7 +48,8 @@ pub enum Qualifier {
    mechanism        = ( all / include
                       / a / mx / ptr / ip4 / ip6 / exists )
 */
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mechanism {
     All,
     Include {
@@ -46,12 +57,28 @@ pub enum Mechanism {
     },
     A {
         macro_string: Macro,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "ip4_cidr_length", with = "serialize::ip4_cidr")
+        )]
         ip4_mask: u32,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "ip6_cidr_length", with = "serialize::ip6_cidr")
+        )]
         ip6_mask: u128,
     },
     Mx {
         macro_string: Macro,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "ip4_cidr_length", with = "serialize::ip4_cidr")
+        )]
         ip4_mask: u32,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "ip6_cidr_length", with = "serialize::ip6_cidr")
+        )]
         ip6_mask: u128,
     },
     Ptr {
@@ -59,10 +86,18 @@ pub enum Mechanism {
     },
     Ip4 {
         addr: Ipv4Addr,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "cidr_length", with = "serialize::ip4_cidr")
+        )]
         mask: u32,
     },
     Ip6 {
         addr: Ipv6Addr,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "cidr_length", with = "serialize::ip6_cidr")
+        )]
         mask: u128,
     },
     Exists {
@@ -73,7 +108,8 @@ pub enum Mechanism {
 /*
     directive        = [ qualifier ] mechanism
 */
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Directive {
     pub qualifier: Qualifier,
     pub mechanism: Mechanism,
@@ -95,7 +131,8 @@ pub struct Directive {
       t = current timestamp
 */
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Variable {
     Sender = 0,
@@ -116,21 +153,97 @@ pub struct Variables<'x> {
     vars: [Cow<'x, [u8]>; 11],
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Macro {
-    Literal(Vec<u8>),
+    Literal(#[cfg_attr(feature = "serde", serde(with = "serialize::literal"))] Vec<u8>),
     Variable {
         letter: Variable,
         num_parts: u32,
         reverse: bool,
         escape: bool,
+        #[cfg_attr(feature = "serde", serde(with = "serialize::delimiters"))]
         delimiters: u64,
     },
     List(Vec<Macro>),
     None,
 }
 
+/*
+    Terms that require a DNS query during evaluation
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LookupTerm<'x> {
+    Include(&'x Macro),
+    A(&'x Macro),
+    Mx(&'x Macro),
+    Ptr(&'x Macro),
+    Exists(&'x Macro),
+    Redirect(&'x Macro),
+}
+
+/*
+    Mechanism evaluated during a traced verification
+*/
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceStep {
+    pub domain: String,
+    pub depth: usize,
+    pub directive: Directive,
+    pub target: Option<String>,
+    pub answers: Vec<String>,
+    pub matched: bool,
+}
+
+/*
+    SPF check submitted as part of a batch verification
+*/
 #[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpfRequest {
+    pub ip: IpAddr,
+    pub helo_domain: String,
+    pub host_domain: String,
+    pub mail_from: String,
+}
+
+/// Identity an SPF check is evaluated for, with the sender normalized as
+/// described in RFC 7208 section 4.3.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpfContext {
+    pub ip: IpAddr,
+    pub helo_domain: String,
+    pub domain: String,
+    pub sender: String,
+}
+
+/// Properties of an evaluated record that a receiver may apply its own
+/// policy to, independently of the SPF result.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SpfPolicyHint {
+    /// The record ends with `+all`, authorizing every host.
+    PassAll,
+    /// The record ends with `?all`, making no assertion about other hosts.
+    NeutralAll,
+}
+
+/*
+    Problems found while validating or flattening a record
+*/
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Warning {
+    UnresolvedMacro(Macro),
+    Unflattenable(String),
+    TooManyLookups,
+    RecordTooLong(usize),
+    DeprecatedPtr,
+    ManyMxPtr(usize),
+    UnknownModifier(String),
+    PassAll,
+    MissingAll,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Spf {
     pub version: Version,
     pub directives: Vec<Directive>,
@@ -139,8 +252,27 @@ pub struct Spf {
     pub ra: Option<Vec<u8>>,
     pub rp: u8,
     pub rr: u8,
+    pub modifiers: Vec<Modifier>,
+}
+
+/*
+    Modifiers not defined by RFC 7208, kept for round-tripping
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Modifier {
+    Unknown(String, Macro),
 }
 
+/// Default maximum length of a domain name produced by macro expansion.
+pub const MAX_EXPANSION_LEN: usize = 253;
+
+/// Default time limit for evaluating an SPF record, including nested records.
+pub const EVALUATION_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Maximum number of checks evaluated concurrently by a batch verification.
+pub const BATCH_CONCURRENCY: usize = 16;
+
 pub(crate) const RR_TEMP_PERM_ERROR: u8 = 0x01;
 pub(crate) const RR_FAIL: u8 = 0x02;
 pub(crate) const RR_SOFTFAIL: u8 = 0x04;
@@ -153,6 +285,14 @@ impl Directive {
             mechanism,
         }
     }
+
+    pub fn qualifier(&self) -> Qualifier {
+        self.qualifier
+    }
+
+    pub fn mechanism(&self) -> &Mechanism {
+        &self.mechanism
+    }
 }
 
 impl Mechanism {
@@ -168,6 +308,142 @@ impl Mechanism {
             Mechanism::Exists { macro_string } => macro_string.needs_ptr(),
         }
     }
+
+    /// Returns `true` if the client IP falls within the network of an
+    /// `ip4` or `ip6` mechanism. Other mechanisms never match.
+    pub fn matches_ip(&self, client: IpAddr) -> bool {
+        match self {
+            Mechanism::Ip4 { addr, mask } => client.matches_ipv4_mask(addr, *mask),
+            Mechanism::Ip6 { addr, mask } => client.matches_ipv6_mask(addr, *mask),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the client IP falls within the network of an address
+    /// resolved for an `a` or `mx` mechanism, using the IPv4 CIDR length for
+    /// A records and the IPv6 CIDR length for AAAA records.
+    pub fn matches_resolved_ip(&self, client: IpAddr, resolved: IpAddr) -> bool {
+        match (self, resolved) {
+            (Mechanism::A { ip4_mask, .. } | Mechanism::Mx { ip4_mask, .. }, IpAddr::V4(addr)) => {
+                client.matches_ipv4_mask(&addr, *ip4_mask)
+            }
+            (Mechanism::A { ip6_mask, .. } | Mechanism::Mx { ip6_mask, .. }, IpAddr::V6(addr)) => {
+                client.matches_ipv6_mask(&addr, *ip6_mask)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the macro-expanded domain an `a`, `mx`, `ptr`, `include` or
+    /// `exists` mechanism would query, using `domain` as the current domain
+    /// for mechanisms without a domain-spec. Returns `None` for `ip4`, `ip6`
+    /// and `all`.
+    pub fn target_domain(&self, vars: &Variables<'_>, domain: &str) -> Option<String> {
+        match self {
+            Mechanism::A { macro_string, .. }
+            | Mechanism::Mx { macro_string, .. }
+            | Mechanism::Ptr { macro_string }
+            | Mechanism::Include { macro_string }
+            | Mechanism::Exists { macro_string } => {
+                expand_target(macro_string, vars, domain).into()
+            }
+            Mechanism::All | Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. } => None,
+        }
+    }
+}
+
+fn expand_target(macro_string: &Macro, vars: &Variables<'_>, domain: &str) -> String {
+    let target = macro_string.eval(vars, domain, true);
+    target.strip_suffix('.').unwrap_or(&target).to_string()
+}
+
+impl Default for Spf {
+    fn default() -> Self {
+        Spf {
+            version: Version::V1,
+            directives: Vec::new(),
+            exp: None,
+            redirect: None,
+            ra: None,
+            rp: 100,
+            rr: u8::MAX,
+            modifiers: Vec::new(),
+        }
+    }
+}
+
+/// Builds a record from its directives, with no modifiers.
+impl FromIterator<Directive> for Spf {
+    fn from_iter<T: IntoIterator<Item = Directive>>(iter: T) -> Self {
+        Spf {
+            directives: iter.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Appends directives to the record, after any existing ones.
+impl Extend<Directive> for Spf {
+    fn extend<T: IntoIterator<Item = Directive>>(&mut self, iter: T) {
+        self.directives.extend(iter);
+    }
+}
+
+impl Spf {
+    /// Returns the terms of this record that require a DNS lookup during evaluation,
+    /// with their macro strings unexpanded.
+    pub fn lookup_terms(&self) -> impl Iterator<Item = LookupTerm<'_>> {
+        self.directives
+            .iter()
+            .filter_map(|directive| match &directive.mechanism {
+                Mechanism::Include { macro_string } => LookupTerm::Include(macro_string).into(),
+                Mechanism::A { macro_string, .. } => LookupTerm::A(macro_string).into(),
+                Mechanism::Mx { macro_string, .. } => LookupTerm::Mx(macro_string).into(),
+                Mechanism::Ptr { macro_string } => LookupTerm::Ptr(macro_string).into(),
+                Mechanism::Exists { macro_string } => LookupTerm::Exists(macro_string).into(),
+                Mechanism::All | Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. } => None,
+            })
+            .chain(self.redirect.as_ref().map(LookupTerm::Redirect))
+    }
+
+    /// Returns the qualifier of the `all` mechanism that terminates evaluation of
+    /// this record, that is the first one, since later directives are never reached.
+    /// When present, any `redirect` modifier is ignored. Returns `None` if the
+    /// record has no `all` mechanism, in which case the result is that of the
+    /// `redirect` target, or neutral if there is none.
+    pub fn all_qualifier(&self) -> Option<Qualifier> {
+        self.directives
+            .iter()
+            .find(|directive| matches!(directive.mechanism, Mechanism::All))
+            .map(|directive| directive.qualifier)
+    }
+
+    /// Removes repeated directives, keeping the first occurrence of each. Later
+    /// copies can never match, so the result of evaluation is unchanged.
+    pub fn dedup(&mut self) {
+        let mut seen = HashSet::with_capacity(self.directives.len());
+        self.directives
+            .retain(|directive| seen.insert(directive.clone()));
+    }
+}
+
+impl<'x> LookupTerm<'x> {
+    pub fn macro_string(&self) -> &'x Macro {
+        match self {
+            LookupTerm::Include(macro_string)
+            | LookupTerm::A(macro_string)
+            | LookupTerm::Mx(macro_string)
+            | LookupTerm::Ptr(macro_string)
+            | LookupTerm::Exists(macro_string)
+            | LookupTerm::Redirect(macro_string) => macro_string,
+        }
+    }
+
+    /// Returns the macro-expanded domain this term would query, using `domain`
+    /// as the current domain for terms without a domain-spec.
+    pub fn target_domain(&self, vars: &Variables<'_>, domain: &str) -> String {
+        expand_target(self.macro_string(), vars, domain)
+    }
 }
 
 impl TryFrom<&str> for SpfResult {
@@ -202,16 +478,221 @@ impl TryFrom<String> for SpfResult {
     }
 }
 
+impl Display for Spf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("v=spf1")?;
+        for directive in &self.directives {
+            write!(f, " {directive}")?;
+        }
+        if let Some(redirect) = &self.redirect {
+            write!(f, " redirect={redirect}")?;
+        }
+        if let Some(exp) = &self.exp {
+            write!(f, " exp={exp}")?;
+        }
+        if let Some(ra) = &self.ra {
+            write!(f, " ra={}", String::from_utf8_lossy(ra))?;
+        }
+        if self.rp != 100 {
+            write!(f, " rp={}", self.rp)?;
+        }
+        if self.rr != u8::MAX {
+            f.write_str(" rr=")?;
+            let mut is_first = true;
+            for (flag, name) in [
+                (RR_TEMP_PERM_ERROR, 'e'),
+                (RR_FAIL, 'f'),
+                (RR_SOFTFAIL, 's'),
+                (RR_NEUTRAL_NONE, 'n'),
+            ] {
+                if (self.rr & flag) != 0 {
+                    if !is_first {
+                        f.write_char(':')?;
+                    }
+                    f.write_char(name)?;
+                    is_first = false;
+                }
+            }
+        }
+        for modifier in &self.modifiers {
+            write!(f, " {modifier}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Modifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Modifier::Unknown(name, value) => write!(f, "{name}={value}"),
+        }
+    }
+}
+
+impl Display for Directive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.qualifier, self.mechanism)
+    }
+}
+
+impl Display for Qualifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Qualifier::Pass => "",
+            Qualifier::Fail => "-",
+            Qualifier::SoftFail => "~",
+            Qualifier::Neutral => "?",
+        })
+    }
+}
+
+impl Display for Mechanism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mechanism::All => f.write_str("all"),
+            Mechanism::Include { macro_string } => write!(f, "include:{macro_string}"),
+            Mechanism::A {
+                macro_string,
+                ip4_mask,
+                ip6_mask,
+            }
+            | Mechanism::Mx {
+                macro_string,
+                ip4_mask,
+                ip6_mask,
+            } => {
+                f.write_str(if matches!(self, Mechanism::A { .. }) {
+                    "a"
+                } else {
+                    "mx"
+                })?;
+                if macro_string != &Macro::None {
+                    write!(f, ":{macro_string}")?;
+                }
+                if *ip4_mask != u32::MAX {
+                    write!(f, "/{}", ip4_mask.count_ones())?;
+                }
+                if *ip6_mask != u128::MAX {
+                    write!(f, "//{}", ip6_mask.count_ones())?;
+                }
+                Ok(())
+            }
+            Mechanism::Ptr { macro_string } => {
+                f.write_str("ptr")?;
+                if macro_string != &Macro::None {
+                    write!(f, ":{macro_string}")?;
+                }
+                Ok(())
+            }
+            Mechanism::Ip4 { addr, mask } => {
+                write!(f, "ip4:{addr}")?;
+                if *mask != u32::MAX {
+                    write!(f, "/{}", mask.count_ones())?;
+                }
+                Ok(())
+            }
+            Mechanism::Ip6 { addr, mask } => {
+                write!(f, "ip6:{addr}")?;
+                if *mask != u128::MAX {
+                    write!(f, "/{}", mask.count_ones())?;
+                }
+                Ok(())
+            }
+            Mechanism::Exists { macro_string } => write!(f, "exists:{macro_string}"),
+        }
+    }
+}
+
+impl Display for Macro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Macro::Literal(literal) => {
+                for &ch in literal {
+                    match ch {
+                        b'%' => f.write_str("%%")?,
+                        b' ' => f.write_str("%_")?,
+                        _ => f.write_char(char::from(ch))?,
+                    }
+                }
+                Ok(())
+            }
+            Macro::Variable {
+                letter,
+                num_parts,
+                reverse,
+                escape,
+                delimiters,
+            } => {
+                let letter = match letter {
+                    Variable::Sender => 's',
+                    Variable::SenderLocalPart => 'l',
+                    Variable::SenderDomainPart => 'o',
+                    Variable::Domain => 'd',
+                    Variable::Ip => 'i',
+                    Variable::ValidatedDomain => 'p',
+                    Variable::IpVersion => 'v',
+                    Variable::HeloDomain => 'h',
+                    Variable::SmtpIp => 'c',
+                    Variable::HostDomain => 'r',
+                    Variable::CurrentTime => 't',
+                };
+                f.write_str("%{")?;
+                f.write_char(if *escape {
+                    letter.to_ascii_uppercase()
+                } else {
+                    letter
+                })?;
+                if *num_parts != 0 {
+                    write!(f, "{num_parts}")?;
+                }
+                if *reverse {
+                    f.write_char('r')?;
+                }
+                if *delimiters != 1u64 << (b'.' - b'+') {
+                    for ch in b".-+,/_=" {
+                        if (delimiters & (1u64 << (ch - b'+'))) != 0 {
+                            f.write_char(char::from(*ch))?;
+                        }
+                    }
+                }
+                f.write_char('}')
+            }
+            Macro::List(list) => {
+                for item in list {
+                    item.fmt(f)?;
+                }
+                Ok(())
+            }
+            Macro::None => Ok(()),
+        }
+    }
+}
+
 impl SpfOutput {
     pub(crate) fn new(domain: String) -> Self {
         SpfOutput {
             result: SpfResult::None,
             report: None,
             explanation: None,
+            matched: None,
+            ip: None,
+            valid_until: None,
+            policy_hint: None,
+            trusted_forwarder: false,
             domain,
         }
     }
 
+    pub(crate) fn with_valid_until(mut self, valid_until: Option<Instant>) -> Self {
+        self.valid_until = valid_until;
+        self
+    }
+
+    pub(crate) fn with_ip(mut self, ip: IpAddr) -> Self {
+        self.ip = ip.into();
+        self
+    }
+
     pub(crate) fn with_result(mut self, result: SpfResult) -> Self {
         self.result = result;
         self
@@ -242,6 +723,27 @@ impl SpfOutput {
         self
     }
 
+    pub(crate) fn with_trusted_forwarder(mut self, mechanism: &Mechanism) -> Self {
+        self.result = SpfResult::Pass;
+        self.matched = Directive::new(Qualifier::Pass, mechanism.clone()).into();
+        self.trusted_forwarder = true;
+        self
+    }
+
+    pub(crate) fn with_matched(mut self, matched: Option<Directive>) -> Self {
+        self.matched = matched;
+        self
+    }
+
+    pub(crate) fn with_policy_hint(mut self, spf: &Spf) -> Self {
+        self.policy_hint = match spf.all_qualifier() {
+            Some(Qualifier::Pass) => SpfPolicyHint::PassAll.into(),
+            Some(Qualifier::Neutral) => SpfPolicyHint::NeutralAll.into(),
+            _ => None,
+        };
+        self
+    }
+
     pub fn result(&self) -> SpfResult {
         self.result
     }
@@ -254,7 +756,99 @@ impl SpfOutput {
         self.explanation.as_deref()
     }
 
+    /// Returns the directive that determined the result, or `None` if the
+    /// result comes from an `all` mechanism or no mechanism matched.
+    pub fn matched_directive(&self) -> Option<&Directive> {
+        self.matched.as_ref()
+    }
+
     pub fn report_address(&self) -> Option<&str> {
         self.report.as_deref()
     }
+
+    /// Returns a hint when the evaluated record ends with `+all` or `?all`,
+    /// for receivers that distrust such permissive policies. The result
+    /// itself is not affected.
+    pub fn policy_hint(&self) -> Option<SpfPolicyHint> {
+        self.policy_hint
+    }
+
+    /// Returns `true` if the result is a Pass because the client is a
+    /// trusted forwarder, in which case no SPF record was evaluated.
+    pub fn is_trusted_forwarder(&self) -> bool {
+        self.trusted_forwarder
+    }
+
+    /// Returns the IP address of the client the record was evaluated for.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.ip
+    }
+
+    /// Returns how long the result remains valid, which is the lowest
+    /// remaining TTL of the DNS records consulted during evaluation, or
+    /// `None` if no record was found.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.valid_until
+            .map(|valid_until| valid_until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Describes the result in plain English for troubleshooting, naming the
+    /// client, the directive that matched and how the record ended. This is
+    /// unrelated to the explanation published with the `exp=` modifier.
+    pub fn explain(&self) -> String {
+        let client = match self.ip {
+            Some(ip) => format!("client {ip}"),
+            None => "client".to_string(),
+        };
+        let domain = &self.domain;
+        match (self.result, &self.matched) {
+            (SpfResult::Pass, Some(directive)) if self.trusted_forwarder => {
+                format!(
+                    "SPF passed for {domain}: {client} is a trusted forwarder in {}",
+                    directive.mechanism
+                )
+            }
+            (SpfResult::Pass, Some(directive)) => {
+                format!("SPF passed for {domain}: {client} matched {directive}")
+            }
+            (SpfResult::Fail | SpfResult::SoftFail | SpfResult::Neutral, Some(directive)) => {
+                format!(
+                    "SPF {} for {domain}: {client} matched {directive}",
+                    self.result.verb()
+                )
+            }
+            (SpfResult::Pass, None) => {
+                format!("SPF passed for {domain}: {client} authorized by +all")
+            }
+            (SpfResult::Fail | SpfResult::SoftFail, None) => format!(
+                "SPF {} for {domain}: {client} not in any authorized mechanism; record ended with {}all",
+                self.result.verb(),
+                if self.result == SpfResult::Fail { '-' } else { '~' }
+            ),
+            (SpfResult::Neutral, None) => {
+                format!("SPF neutral for {domain}: {client} not in any authorized mechanism")
+            }
+            (SpfResult::TempError, _) => format!(
+                "SPF temporary error for {domain}: the record could not be retrieved or evaluated in time"
+            ),
+            (SpfResult::PermError, _) => format!(
+                "SPF permanent error for {domain}: the record is invalid or exceeds the evaluation limits"
+            ),
+            (SpfResult::None, _) => format!("SPF none for {domain}: no SPF record found"),
+        }
+    }
+}
+
+impl SpfResult {
+    fn verb(&self) -> &'static str {
+        match self {
+            SpfResult::Pass => "passed",
+            SpfResult::Fail => "failed",
+            SpfResult::SoftFail => "soft failed",
+            SpfResult::Neutral => "neutral",
+            SpfResult::TempError => "temporary error",
+            SpfResult::PermError => "permanent error",
+            SpfResult::None => "none",
+        }
+    }
 }
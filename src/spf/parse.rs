@@ -11,16 +11,17 @@
 use std::{
     net::{Ipv4Addr, Ipv6Addr},
     slice::Iter,
+    str::FromStr,
 };
 
 use crate::{
     common::parse::{TagParser, TxtRecordParser, V},
-    Error, Version,
+    Error,
 };
 
 use super::{
-    Directive, Macro, Mechanism, Qualifier, Spf, Variable, RR_FAIL, RR_NEUTRAL_NONE, RR_SOFTFAIL,
-    RR_TEMP_PERM_ERROR,
+    Directive, Macro, Mechanism, Modifier, Qualifier, Spf, Variable, RR_FAIL, RR_NEUTRAL_NONE,
+    RR_SOFTFAIL, RR_TEMP_PERM_ERROR,
 };
 
 impl TxtRecordParser for Spf {
@@ -31,19 +32,20 @@ impl TxtRecordParser for Spf {
             || record.next().map_or(false, |v| !v.is_ascii_whitespace())
         {
             return Err(Error::InvalidRecordType);
+        } else if bytes
+            .iter()
+            .any(|ch| ch.is_ascii_control() && !ch.is_ascii_whitespace())
+        {
+            // Control bytes are never valid in a record, report them separately
+            // from malformed terms to make mangled DNS data easier to spot.
+            return Err(Error::InvalidCharacter);
         }
 
-        let mut spf = Spf {
-            version: Version::V1,
-            directives: Vec::new(),
-            redirect: None,
-            exp: None,
-            ra: None,
-            rp: 100,
-            rr: u8::MAX,
-        };
+        let mut spf = Spf::default();
 
-        while let Some((term, qualifier, mut stop_char)) = record.next_term() {
+        let mut term_start = record.as_slice();
+        while let Some((term, explicit_qualifier, mut stop_char)) = record.next_term() {
+            let qualifier = explicit_qualifier.unwrap_or(Qualifier::Pass);
             match term {
                 A | MX => {
                     let mut ip4_cidr_length = 32;
@@ -71,19 +73,26 @@ impl TxtRecordParser for Spf {
                         _ => return Err(Error::ParseError),
                     }
 
+                    // A zero length CIDR matches every address
+                    let ip4_mask = u32::MAX
+                        .checked_shl(32 - ip4_cidr_length as u32)
+                        .unwrap_or(0);
+                    let ip6_mask = u128::MAX
+                        .checked_shl(128 - ip6_cidr_length as u32)
+                        .unwrap_or(0);
                     spf.directives.push(Directive::new(
                         qualifier,
                         if term == A {
                             Mechanism::A {
                                 macro_string,
-                                ip4_mask: u32::MAX << (32 - ip4_cidr_length),
-                                ip6_mask: u128::MAX << (128 - ip6_cidr_length),
+                                ip4_mask,
+                                ip6_mask,
                             }
                         } else {
                             Mechanism::Mx {
                                 macro_string,
-                                ip4_mask: u32::MAX << (32 - ip4_cidr_length),
-                                ip6_mask: u128::MAX << (128 - ip6_cidr_length),
+                                ip4_mask,
+                                ip6_mask,
                             }
                         },
                     ));
@@ -129,7 +138,7 @@ impl TxtRecordParser for Spf {
                         qualifier,
                         Mechanism::Ip4 {
                             addr,
-                            mask: u32::MAX << (32 - cidr_length),
+                            mask: u32::MAX.checked_shl(32 - cidr_length as u32).unwrap_or(0),
                         },
                     ));
                 }
@@ -148,7 +157,7 @@ impl TxtRecordParser for Spf {
                         qualifier,
                         Mechanism::Ip6 {
                             addr,
-                            mask: u128::MAX << (128 - cidr_length),
+                            mask: u128::MAX.checked_shl(128 - cidr_length as u32).unwrap_or(0),
                         },
                     ));
                 }
@@ -168,7 +177,8 @@ impl TxtRecordParser for Spf {
                     }
                 }
                 EXP | REDIRECT => {
-                    if stop_char != b'=' {
+                    // Modifiers do not take a qualifier
+                    if stop_char != b'=' || explicit_qualifier.is_some() {
                         return Err(Error::ParseError);
                     }
                     let (macro_string, stop_char) = record.macro_string(false)?;
@@ -199,17 +209,50 @@ impl TxtRecordParser for Spf {
                 RR => {
                     spf.rr = record.rr()?;
                 }
+                V if stop_char == b'=' => {
+                    // Any other v= is an unknown modifier, but a repeated version
+                    // usually means that two records were merged into one.
+                    let (version, stop_char) = record.macro_string(false)?;
+                    if stop_char != b' '
+                        || matches!(&version, Macro::Literal(v) if v.eq_ignore_ascii_case(b"spf1"))
+                    {
+                        return Err(Error::ParseError);
+                    }
+                }
                 _ => {
-                    let (_, stop_char) = record.macro_string(false)?;
+                    // Unknown terms carry a value only when followed by a stop char,
+                    // otherwise the next term would be consumed as their value.
                     if stop_char != b' ' {
-                        return Err(Error::ParseError);
+                        let (value, next_stop_char) = record.macro_string(false)?;
+                        if next_stop_char != b' ' {
+                            return Err(Error::ParseError);
+                        }
+                        if stop_char == b'=' && explicit_qualifier.is_none() {
+                            if let Some(name) = term_start.modifier_name() {
+                                spf.modifiers.push(Modifier::Unknown(name, value));
+                            }
+                        }
                     }
                 }
             }
+            term_start = record.as_slice();
         }
 
         Ok(spf)
     }
+
+    // RFC 7208 section 4.5: more than one SPF record is a permanent error
+    fn allows_multiple_records() -> bool {
+        false
+    }
+}
+
+impl FromStr for Spf {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Spf::parse(s.as_bytes())
+    }
 }
 
 const A: u64 = b'a' as u64;
@@ -244,8 +287,10 @@ const RA: u64 = (b'a' as u64) << 8 | (b'r' as u64);
 const RP: u64 = (b'p' as u64) << 8 | (b'r' as u64);
 const RR: u64 = (b'r' as u64) << 8 | (b'r' as u64);
 
+const MAX_MACRO_PARTS: u32 = 128;
+
 pub(crate) trait SPFParser: Sized {
-    fn next_term(&mut self) -> Option<(u64, Qualifier, u8)>;
+    fn next_term(&mut self) -> Option<(u64, Option<Qualifier>, u8)>;
     fn macro_string(&mut self, is_exp: bool) -> crate::Result<(Macro, u8)>;
     fn ip4(&mut self) -> crate::Result<(Ipv4Addr, u8)>;
     fn ip6(&mut self) -> crate::Result<(Ipv6Addr, u8)>;
@@ -255,9 +300,30 @@ pub(crate) trait SPFParser: Sized {
     fn ra(&mut self) -> crate::Result<Vec<u8>>;
 }
 
+trait ModifierName {
+    fn modifier_name(&self) -> Option<String>;
+}
+
+impl ModifierName for &[u8] {
+    // RFC 7208 section 6: name = ALPHA *( ALPHA / DIGIT / "-" / "_" / "." )
+    fn modifier_name(&self) -> Option<String> {
+        let term = self.trim_ascii_start();
+        let name = &term[..term.iter().position(|&ch| ch == b'=')?];
+        if name.first().is_some_and(|ch| ch.is_ascii_alphabetic())
+            && name
+                .iter()
+                .all(|&ch| ch.is_ascii_alphanumeric() || matches!(ch, b'-' | b'_' | b'.'))
+        {
+            Some(String::from_utf8_lossy(name).into_owned())
+        } else {
+            None
+        }
+    }
+}
+
 impl SPFParser for Iter<'_, u8> {
-    fn next_term(&mut self) -> Option<(u64, Qualifier, u8)> {
-        let mut qualifier = Qualifier::Pass;
+    fn next_term(&mut self) -> Option<(u64, Option<Qualifier>, u8)> {
+        let mut qualifier = None;
         let mut stop_char = b' ';
         let mut d = 0;
         let mut shift = 0;
@@ -273,16 +339,16 @@ impl SPFParser for Iter<'_, u8> {
                     shift += 8;
                 }
                 b'+' if shift == 0 => {
-                    qualifier = Qualifier::Pass;
+                    qualifier = Some(Qualifier::Pass);
                 }
                 b'-' if shift == 0 => {
-                    qualifier = Qualifier::Fail;
+                    qualifier = Some(Qualifier::Fail);
                 }
                 b'~' if shift == 0 => {
-                    qualifier = Qualifier::SoftFail;
+                    qualifier = Some(Qualifier::SoftFail);
                 }
                 b'?' if shift == 0 => {
-                    qualifier = Qualifier::Neutral;
+                    qualifier = Some(Qualifier::Neutral);
                 }
                 b':' | b'=' | b'/' => {
                     stop_char = ch;
@@ -350,6 +416,7 @@ impl SPFParser for Iter<'_, u8> {
                         })
                         .ok_or(Error::ParseError)?;
                     let mut num_parts: u32 = 0;
+                    let mut has_num_parts = false;
                     let mut reverse = false;
                     let mut delimiters = 0;
 
@@ -359,6 +426,7 @@ impl SPFParser for Iter<'_, u8> {
                                 num_parts = num_parts
                                     .saturating_mul(10)
                                     .saturating_add((ch - b'0') as u32);
+                                has_num_parts = true;
                             }
                             b'r' | b'R' => {
                                 reverse = true;
@@ -375,6 +443,12 @@ impl SPFParser for Iter<'_, u8> {
                         }
                     }
 
+                    // The number of parts has to be positive, and no domain
+                    // name has more than 128 labels
+                    if has_num_parts && !(1..=MAX_MACRO_PARTS).contains(&num_parts) {
+                        return Err(Error::InvalidMacro);
+                    }
+
                     if delimiters == 0 {
                         delimiters = 1u64 << (b'.' - b'+');
                     }
@@ -451,8 +525,14 @@ impl SPFParser for Iter<'_, u8> {
         let mut ip_part = [0u8; 8];
         let mut ip_part_pos = 0;
         let mut zero_group_pos = usize::MAX;
+        let mut last_ch = 0;
+        let mut prev_ch = 0;
 
         for &ch in self {
+            // A single leading colon is only valid as the start of "::"
+            if ch != b':' && last_ch == b':' && prev_ch == 0 {
+                return Err(Error::ParseError);
+            }
             match ch {
                 b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => {
                     if ip_part_pos < 4 {
@@ -466,15 +546,16 @@ impl SPFParser for Iter<'_, u8> {
                     if ip_pos < 8 {
                         if ip_part_pos != 0 {
                             ip[ip_pos] = u16::from_str_radix(
-                                std::str::from_utf8(&ip_part[..ip_part_pos]).unwrap(),
+                                std::str::from_utf8(&ip_part[..ip_part_pos])
+                                    .map_err(|_| Error::ParseError)?,
                                 16,
                             )
                             .map_err(|_| Error::ParseError)?;
                             ip_part_pos = 0;
                             ip_pos += 1;
-                        } else if zero_group_pos == usize::MAX {
+                        } else if last_ch == b':' && zero_group_pos == usize::MAX {
                             zero_group_pos = ip_pos;
-                        } else if zero_group_pos != ip_pos {
+                        } else if last_ch != 0 {
                             return Err(Error::ParseError);
                         }
                     } else {
@@ -484,7 +565,7 @@ impl SPFParser for Iter<'_, u8> {
                 b'.' => {
                     if ip_pos < 8 && ip_part_pos > 0 {
                         let qnum = std::str::from_utf8(&ip_part[..ip_part_pos])
-                            .unwrap()
+                            .map_err(|_| Error::ParseError)?
                             .parse::<u8>()
                             .map_err(|_| Error::ParseError)?
                             as u16;
@@ -505,17 +586,28 @@ impl SPFParser for Iter<'_, u8> {
                     break;
                 }
             }
+            prev_ch = last_ch;
+            last_ch = ch;
+        }
+
+        // Neither can the address end with a single colon
+        if last_ch == b':' && prev_ch != b':' {
+            return Err(Error::ParseError);
         }
 
         if ip_part_pos != 0 {
             if ip_pos < 8 {
                 ip[ip_pos] = if ip4_pos == 0 {
-                    u16::from_str_radix(std::str::from_utf8(&ip_part[..ip_part_pos]).unwrap(), 16)
-                        .map_err(|_| Error::ParseError)?
+                    u16::from_str_radix(
+                        std::str::from_utf8(&ip_part[..ip_part_pos])
+                            .map_err(|_| Error::ParseError)?,
+                        16,
+                    )
+                    .map_err(|_| Error::ParseError)?
                 } else if ip4_pos == 3 {
                     (ip[ip_pos] << 8)
                         | std::str::from_utf8(&ip_part[..ip_part_pos])
-                            .unwrap()
+                            .map_err(|_| Error::ParseError)?
                             .parse::<u8>()
                             .map_err(|_| Error::ParseError)? as u16
                 } else {
@@ -733,14 +825,18 @@ impl TxtRecordParser for Macro {
 
 #[cfg(test)]
 mod test {
-    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::{
+        collections::HashSet,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
 
     use crate::{
         common::parse::TxtRecordParser,
         spf::{
-            Directive, Macro, Mechanism, Qualifier, Spf, Variable, Version, RR_FAIL,
-            RR_NEUTRAL_NONE, RR_SOFTFAIL, RR_TEMP_PERM_ERROR,
+            Directive, LookupTerm, Macro, Mechanism, Modifier, Qualifier, Spf, Variable, Version,
+            RR_FAIL, RR_NEUTRAL_NONE, RR_SOFTFAIL, RR_TEMP_PERM_ERROR,
         },
+        Error,
     };
 
     use super::SPFParser;
@@ -755,6 +851,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -785,6 +882,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -807,6 +905,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -829,6 +928,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     redirect: Macro::Literal(b"_spf.example.com".to_vec()).into(),
                     exp: None,
                     directives: vec![Directive::new(
@@ -848,6 +948,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -878,6 +979,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -904,6 +1006,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -948,6 +1051,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: Macro::List(vec![
                         Macro::Literal(b"explain._spf.".to_vec()),
                         Macro::Variable {
@@ -980,6 +1084,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1008,6 +1113,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1037,6 +1143,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1067,6 +1174,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1087,6 +1195,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![Directive::new(
@@ -1120,6 +1229,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![Directive::new(
@@ -1161,6 +1271,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1222,6 +1333,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1259,6 +1371,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1303,6 +1416,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: Macro::List(vec![
                         Macro::Variable {
@@ -1350,6 +1464,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1382,6 +1497,7 @@ mod test {
                     ra: None,
                     rp: 100,
                     rr: u8::MAX,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1432,6 +1548,7 @@ mod test {
                     ra: b"postmaster".to_vec().into(),
                     rp: 15,
                     rr: RR_FAIL | RR_NEUTRAL_NONE | RR_SOFTFAIL | RR_TEMP_PERM_ERROR,
+                    modifiers: vec![],
                     exp: None,
                     redirect: None,
                     directives: vec![
@@ -1447,6 +1564,28 @@ mod test {
                     ],
                 },
             ),
+            (
+                "v=spf1 verylongmodifier=value redirectx=example.org verylongmechanism -all",
+                Spf {
+                    version: Version::V1,
+                    ra: None,
+                    rp: 100,
+                    rr: u8::MAX,
+                    modifiers: vec![
+                        Modifier::Unknown(
+                            "verylongmodifier".to_string(),
+                            Macro::Literal(b"value".to_vec()),
+                        ),
+                        Modifier::Unknown(
+                            "redirectx".to_string(),
+                            Macro::Literal(b"example.org".to_vec()),
+                        ),
+                    ],
+                    exp: None,
+                    redirect: None,
+                    directives: vec![Directive::new(Qualifier::Fail, Mechanism::All)],
+                },
+            ),
         ] {
             assert_eq!(
                 Spf::parse(record.as_bytes()).unwrap_or_else(|err| panic!("{record:?} : {err:?}")),
@@ -1456,6 +1595,457 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_spf_version() {
+        for record in [
+            "V=spf1 ip4:192.0.2.0/24 -all",
+            "v=SPF1 ip4:192.0.2.0/24 -all",
+            "v=spf1\tip4:192.0.2.0/24\t-all",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap().directives,
+                vec![
+                    Directive::new(
+                        Qualifier::Pass,
+                        Mechanism::Ip4 {
+                            addr: "192.0.2.0".parse().unwrap(),
+                            mask: u32::MAX << 8,
+                        },
+                    ),
+                    Directive::new(Qualifier::Fail, Mechanism::All),
+                ],
+                "{record:?}"
+            );
+        }
+
+        for record in [
+            "v=spf1 a:example.org\0 -all",
+            "v=spf1 \0include:example.org -all",
+            "v=spf1 ip4:192.0.2.1 -all\x7f",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::InvalidCharacter,
+                "{record:?}"
+            );
+        }
+        assert_eq!(
+            Spf::parse(b"v=spf2 \0 -all").unwrap_err(),
+            Error::InvalidRecordType
+        );
+
+        for record in ["v=spf2 -all", "v=spf2.0/pra -all", "v=spf10 -all"] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::InvalidRecordType,
+                "{record:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_spf_final_token() {
+        for record in [
+            "v=spf1 a",
+            "v=spf1 a:example.org",
+            "v=spf1 a/24",
+            "v=spf1 a:example.org/24//64",
+            "v=spf1 mx",
+            "v=spf1 mx:example.org",
+            "v=spf1 mx//64",
+            "v=spf1 ptr",
+            "v=spf1 ptr:example.org",
+            "v=spf1 include:example.org",
+            "v=spf1 exists:%{i}.example.org",
+            "v=spf1 -ip4:1.2.3.4",
+            "v=spf1 ip4:1.2.3.0/24",
+            "v=spf1 ip6:2001:db8::1",
+            "v=spf1 ip6:2001:db8::/32",
+            "v=spf1 redirect=a.com",
+            "v=spf1 redirect=%{d}.a.com",
+            "v=spf1 -all exp=explain.%{d}",
+            "v=spf1 -all ra=postmaster",
+            "v=spf1 -all rp=50",
+            "v=spf1 -all rr=e:f",
+            "v=spf1 -all unknown=value",
+        ] {
+            let spf = Spf::parse(record.as_bytes())
+                .unwrap_or_else(|err| panic!("{record:?} failed to parse: {err}"));
+            let spf_ws = Spf::parse(format!("{record} ").as_bytes()).unwrap();
+            assert_eq!(spf, spf_ws, "{record:?}");
+        }
+    }
+
+    #[test]
+    fn parse_spf_whitespace_separators() {
+        let record = concat!(
+            "v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 a:example.org/24 mx ",
+            "include:_spf.example.org exists:%{i}.example.org ~all ",
+            "exp=explain.%{d} foo=bar"
+        );
+        let expected = Spf::parse(record.as_bytes()).unwrap();
+
+        for separator in ["\t", "\r\n", "\r", "\n", " \t\r\n ", "\r\n\r\n"] {
+            let record_ = record.replace(' ', separator);
+            assert_eq!(
+                Spf::parse(record_.as_bytes())
+                    .unwrap_or_else(|err| panic!("{record_:?} failed to parse: {err}")),
+                expected,
+                "{record_:?}"
+            );
+            let record_ = format!("{record_}{separator}");
+            assert_eq!(
+                Spf::parse(record_.as_bytes()).unwrap(),
+                expected,
+                "{record_:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_spf_repeated_version() {
+        for record in [
+            "v=spf1 v=spf1 -all",
+            "v=spf1 redirect=a.com v=spf1",
+            "v=spf1 a -all V=SPF1 mx -all",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::ParseError,
+                "{record:?}"
+            );
+        }
+
+        // Other values are unknown modifiers
+        assert_eq!(
+            Spf::parse(b"v=spf1 mx v=other -all").unwrap(),
+            Spf::parse(b"v=spf1 mx -all").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_spf_modifier_qualifier() {
+        for record in [
+            "v=spf1 -redirect=x.com",
+            "v=spf1 +redirect=x.com",
+            "v=spf1 mx ~redirect=_spf.%{d}",
+            "v=spf1 -all ?exp=explain.x.com",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::ParseError,
+                "{record:?}"
+            );
+        }
+
+        let spf = Spf::parse(b"v=spf1 -mx redirect=x.com").unwrap();
+        assert_eq!(spf.directives[0].qualifier(), Qualifier::Fail);
+        assert_eq!(spf.redirect, Some(Macro::Literal(b"x.com".to_vec())));
+    }
+
+    #[test]
+    fn spf_dedup() {
+        let mut spf = Spf::parse(
+            b"v=spf1 ip4:192.0.2.1 mx ip4:192.0.2.1 -ip4:192.0.2.1 ip4:192.0.2.1/24 MX -all",
+        )
+        .unwrap();
+        spf.dedup();
+        assert_eq!(
+            spf,
+            Spf::parse(b"v=spf1 ip4:192.0.2.1 mx -ip4:192.0.2.1 ip4:192.0.2.1/24 -all").unwrap()
+        );
+
+        let directives = Spf::parse(b"v=spf1 a:%{d}.x.com include:x.com a:%{d}.x.com")
+            .unwrap()
+            .directives
+            .into_iter()
+            .collect::<HashSet<_>>();
+        assert_eq!(directives.len(), 2);
+    }
+
+    #[test]
+    fn spf_directive_accessors() {
+        let spf =
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 ~mx -include:_spf.example.org ?all").unwrap();
+        assert_eq!(
+            spf.directives
+                .iter()
+                .map(|d| (d.qualifier(), d.mechanism().to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Qualifier::Pass, "ip4:192.0.2.0/24".to_string()),
+                (Qualifier::SoftFail, "mx".to_string()),
+                (Qualifier::Fail, "include:_spf.example.org".to_string()),
+                (Qualifier::Neutral, "all".to_string()),
+            ]
+        );
+        assert!(matches!(
+            spf.directives[1].mechanism(),
+            Mechanism::Mx {
+                macro_string: Macro::None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_spf_domain_dual_cidr() {
+        let domain = Macro::Variable {
+            letter: Variable::Domain,
+            num_parts: 0,
+            reverse: false,
+            escape: false,
+            delimiters: 1u64 << (b'.' - b'+'),
+        };
+        for (record, expected) in [
+            (
+                "v=spf1 a:host.example.com/24/64",
+                Mechanism::A {
+                    macro_string: Macro::Literal(b"host.example.com".to_vec()),
+                    ip4_mask: u32::MAX << (32 - 24),
+                    ip6_mask: u128::MAX << (128 - 64),
+                },
+            ),
+            (
+                "v=spf1 a:%{d}/24",
+                Mechanism::A {
+                    macro_string: domain.clone(),
+                    ip4_mask: u32::MAX << (32 - 24),
+                    ip6_mask: u128::MAX,
+                },
+            ),
+            (
+                "v=spf1 a:%{d}//64",
+                Mechanism::A {
+                    macro_string: domain.clone(),
+                    ip4_mask: u32::MAX,
+                    ip6_mask: u128::MAX << (128 - 64),
+                },
+            ),
+            (
+                "v=spf1 a:mail.%{d}/28/96",
+                Mechanism::A {
+                    macro_string: Macro::List(vec![Macro::Literal(b"mail.".to_vec()), domain]),
+                    ip4_mask: u32::MAX << (32 - 28),
+                    ip6_mask: u128::MAX << (128 - 96),
+                },
+            ),
+            (
+                "v=spf1 mx:ex.org//64",
+                Mechanism::Mx {
+                    macro_string: Macro::Literal(b"ex.org".to_vec()),
+                    ip4_mask: u32::MAX,
+                    ip6_mask: u128::MAX << (128 - 64),
+                },
+            ),
+            (
+                "v=spf1 mx:ex.org/0/0",
+                Mechanism::Mx {
+                    macro_string: Macro::Literal(b"ex.org".to_vec()),
+                    ip4_mask: 0,
+                    ip6_mask: 0,
+                },
+            ),
+        ] {
+            let spf = Spf::parse(record.as_bytes())
+                .unwrap_or_else(|err| panic!("{record:?} failed to parse: {err}"));
+            assert_eq!(spf.directives[0].mechanism(), &expected, "{record:?}");
+            assert_eq!(
+                Spf::parse(spf.to_string().as_bytes()).unwrap(),
+                spf,
+                "{record:?}"
+            );
+        }
+
+        for (record, expected) in [
+            (
+                "v=spf1 ip4:0.0.0.0/0",
+                Mechanism::Ip4 {
+                    addr: Ipv4Addr::UNSPECIFIED,
+                    mask: 0,
+                },
+            ),
+            (
+                "v=spf1 ip6:::/0",
+                Mechanism::Ip6 {
+                    addr: Ipv6Addr::UNSPECIFIED,
+                    mask: 0,
+                },
+            ),
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap().directives[0].mechanism(),
+                &expected,
+                "{record:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn spf_all_qualifier() {
+        for (record, expected) in [
+            ("v=spf1 mx -all", Some(Qualifier::Fail)),
+            ("v=spf1 mx ~all", Some(Qualifier::SoftFail)),
+            ("v=spf1 mx ?all", Some(Qualifier::Neutral)),
+            ("v=spf1 mx all", Some(Qualifier::Pass)),
+            ("v=spf1 mx +all", Some(Qualifier::Pass)),
+            ("v=spf1 ~all -all", Some(Qualifier::SoftFail)),
+            (
+                "v=spf1 -all redirect=_spf.example.org",
+                Some(Qualifier::Fail),
+            ),
+            ("v=spf1 mx redirect=_spf.example.org", None),
+            ("v=spf1 mx", None),
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap().all_qualifier(),
+                expected,
+                "{record}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_spf_all() {
+        for (record, qualifier) in [
+            ("v=spf1 ?all", Qualifier::Neutral),
+            ("v=spf1 -all ", Qualifier::Fail),
+            ("v=spf1 mx ~all", Qualifier::SoftFail),
+            ("v=spf1 mx\tALL", Qualifier::Pass),
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes())
+                    .unwrap()
+                    .directives
+                    .last()
+                    .unwrap(),
+                &Directive::new(qualifier, Mechanism::All),
+                "{record:?}"
+            );
+        }
+
+        for record in [
+            "v=spf1 -all:x",
+            "v=spf1 -all:",
+            "v=spf1 ?all/24",
+            "v=spf1 all=x",
+            "v=spf1 mx -all:example.org ",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::ParseError,
+                "{record:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_spf_macro_num_parts() {
+        for (record, num_parts) in [
+            ("v=spf1 exists:%{d1}.example.org -all", 1),
+            ("v=spf1 exists:%{d128r}.example.org -all", 128),
+            ("v=spf1 exists:%{d007}.example.org -all", 7),
+        ] {
+            match &Spf::parse(record.as_bytes()).unwrap().directives[0].mechanism {
+                Mechanism::Exists {
+                    macro_string: Macro::List(list),
+                } => assert!(
+                    matches!(list[0], Macro::Variable { num_parts: n, .. } if n == num_parts),
+                    "{record}"
+                ),
+                mechanism => panic!("Unexpected mechanism {mechanism:?}"),
+            }
+        }
+
+        for record in [
+            "v=spf1 exists:%{d0}.example.org -all",
+            "v=spf1 exists:%{d00r}.example.org -all",
+            "v=spf1 exists:%{d129}.example.org -all",
+            "v=spf1 exists:%{d999999999999}.example.org -all",
+            "v=spf1 -all exp=%{d0}.example.org",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::InvalidMacro,
+                "{record}"
+            );
+        }
+    }
+
+    #[test]
+    fn spf_lookup_terms() {
+        let spf = Spf::parse(
+            concat!(
+                "v=spf1 include:_spf.example.org include:%{d}._spf.example.org ",
+                "ip4:192.0.2.1 a mx:mx.example.org ptr exists:%{i}._spf.example.org ",
+                "-all redirect=_spf2.example.org"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let terms = spf.lookup_terms().collect::<Vec<_>>();
+
+        assert_eq!(terms.len(), 7);
+        assert_eq!(
+            terms,
+            vec![
+                LookupTerm::Include(&Macro::Literal(b"_spf.example.org".to_vec())),
+                LookupTerm::Include(&Macro::List(vec![
+                    Macro::Variable {
+                        letter: Variable::Domain,
+                        num_parts: 0,
+                        reverse: false,
+                        escape: false,
+                        delimiters: 1u64 << (b'.' - b'+'),
+                    },
+                    Macro::Literal(b"._spf.example.org".to_vec()),
+                ])),
+                LookupTerm::A(&Macro::None),
+                LookupTerm::Mx(&Macro::Literal(b"mx.example.org".to_vec())),
+                LookupTerm::Ptr(&Macro::None),
+                LookupTerm::Exists(&Macro::List(vec![
+                    Macro::Variable {
+                        letter: Variable::Ip,
+                        num_parts: 0,
+                        reverse: false,
+                        escape: false,
+                        delimiters: 1u64 << (b'.' - b'+'),
+                    },
+                    Macro::Literal(b"._spf.example.org".to_vec()),
+                ])),
+                LookupTerm::Redirect(&Macro::Literal(b"_spf2.example.org".to_vec())),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ip6_random_bytes() {
+        // Xorshift PRNG to avoid pulling in a dependency
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let alphabet = b"0123456789abcdefABCDEF:.:.: /";
+
+        for _ in 0..100_000 {
+            let len = (next() % 48) as usize;
+            let bytes = (0..len)
+                .map(|_| {
+                    let rnd = next();
+                    if rnd % 8 == 0 {
+                        (rnd >> 8) as u8
+                    } else {
+                        alphabet[(rnd >> 8) as usize % alphabet.len()]
+                    }
+                })
+                .collect::<Vec<_>>();
+            let _ = bytes.iter().ip6();
+        }
+    }
+
     #[test]
     fn parse_ip6() {
         for test in [
@@ -1495,6 +2085,14 @@ mod test {
             "0:0:0:0:0:0:13.1.68.3.4",
             "::0:0:0:0:0:0:0:0",
             "0:0:0:0::0:0:0:0",
+            "::1::2",
+            "1::2::3",
+            ":::",
+            "1:::2",
+            "1:",
+            "1:2:3:4:5:6:7:",
+            ":1:2",
+            ":",
             " ",
             "",
         ] {
@@ -1504,6 +2102,19 @@ mod test {
                 invalid_test
             );
         }
+
+        for record in [
+            "v=spf1 ip6:::1::2 -all",
+            "v=spf1 ip6:1::2::3 -all",
+            "v=spf1 ip6:1: -all",
+            "v=spf1 ip6:2001:db8:/32 -all",
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap_err(),
+                Error::ParseError,
+                "{record:?}"
+            );
+        }
     }
 
     #[test]
@@ -1520,4 +2131,79 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn spf_display() {
+        for record in [
+            "v=spf1 ip4:192.0.2.0/24 -ip6:2001:db8::/32 ~a ?mx:mx.example.org/30//64 -all",
+            "v=spf1 include:_spf.example.org exists:%{ir}.%{l1r-+}._spf.%{d} ptr ptr:%{D2}",
+            "v=spf1 a/24 redirect=_spf.example.org exp=explain.%{d}",
+            "v=spf1 -all ra=postmaster rp=50 rr=e:f",
+            "v=spf1 exists:%{s}%%%_.example.org -all",
+            "v=spf1 -all foo=bar x-test.v2=%{d}.example.org",
+        ] {
+            let spf = Spf::parse(record.as_bytes()).unwrap();
+            assert_eq!(spf.to_string(), record);
+            assert_eq!(Spf::parse(spf.to_string().as_bytes()).unwrap(), spf);
+        }
+    }
+
+    #[test]
+    fn spf_from_directives() {
+        let directives = vec![
+            Directive::new(
+                Qualifier::Pass,
+                Mechanism::Ip4 {
+                    addr: Ipv4Addr::new(192, 0, 2, 0),
+                    mask: u32::MAX << 8,
+                },
+            ),
+            Directive::new(
+                Qualifier::Pass,
+                Mechanism::Mx {
+                    macro_string: Macro::None,
+                    ip4_mask: u32::MAX,
+                    ip6_mask: u128::MAX,
+                },
+            ),
+        ];
+        let mut spf = directives.into_iter().collect::<Spf>();
+        assert_eq!(spf.to_string(), "v=spf1 ip4:192.0.2.0/24 mx");
+
+        spf.extend([Directive::new(Qualifier::SoftFail, Mechanism::All)]);
+        spf.rr = RR_FAIL;
+        assert_eq!(spf.to_string(), "v=spf1 ip4:192.0.2.0/24 mx ~all rr=f");
+        assert_eq!(Spf::parse(spf.to_string().as_bytes()).unwrap(), spf);
+        assert_eq!(
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 mx ~all")
+                .unwrap()
+                .directives
+                .into_iter()
+                .collect::<Spf>(),
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 mx ~all").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_spf_unknown_modifier() {
+        let spf = Spf::parse(b"v=spf1 foo=bar -all").unwrap();
+        assert_eq!(
+            spf.modifiers,
+            vec![Modifier::Unknown(
+                "foo".to_string(),
+                Macro::Literal(b"bar".to_vec())
+            )]
+        );
+        assert_eq!(spf.to_string(), "v=spf1 -all foo=bar");
+        assert_eq!(Spf::parse(spf.to_string().as_bytes()).unwrap(), spf);
+
+        // Unknown mechanisms and qualified terms are not modifiers
+        for record in ["v=spf1 foo:bar -all", "v=spf1 -foo=bar -all"] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap().modifiers,
+                vec![],
+                "{record:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+// Field adapters giving SPF records a readable serde representation:
+// network masks are written as CIDR lengths, macro delimiters as the
+// list of delimiter characters and literals as strings.
+
+const DELIMITERS: &[u8] = b".-+,/_=";
+
+pub(crate) mod ip4_cidr {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mask: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(mask.count_ones() as u8)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        match u8::deserialize(deserializer)? {
+            cidr_length @ 0..=32 => Ok(u32::MAX.checked_shl(32 - cidr_length as u32).unwrap_or(0)),
+            cidr_length => Err(D::Error::custom(format!(
+                "invalid IPv4 CIDR length {cidr_length}"
+            ))),
+        }
+    }
+}
+
+pub(crate) mod ip6_cidr {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mask: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(mask.count_ones() as u8)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        match u8::deserialize(deserializer)? {
+            cidr_length @ 0..=128 => {
+                Ok(u128::MAX.checked_shl(128 - cidr_length as u32).unwrap_or(0))
+            }
+            cidr_length => Err(D::Error::custom(format!(
+                "invalid IPv6 CIDR length {cidr_length}"
+            ))),
+        }
+    }
+}
+
+pub(crate) mod delimiters {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::DELIMITERS;
+
+    pub fn serialize<S: Serializer>(delimiters: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut result = String::with_capacity(DELIMITERS.len());
+        for &ch in DELIMITERS {
+            if (delimiters & (1u64 << (ch - b'+'))) != 0 {
+                result.push(char::from(ch));
+            }
+        }
+        serializer.serialize_str(&result)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let mut delimiters = 0;
+        for ch in String::deserialize(deserializer)?.bytes() {
+            if DELIMITERS.contains(&ch) {
+                delimiters |= 1u64 << (ch - b'+');
+            } else {
+                return Err(D::Error::custom(format!(
+                    "invalid macro delimiter {:?}",
+                    char::from(ch)
+                )));
+            }
+        }
+        Ok(delimiters)
+    }
+}
+
+pub(crate) mod literal {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(literal: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(literal))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        String::deserialize(deserializer).map(String::into_bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::{crypto::Algorithm, parse::TxtRecordParser},
+        dkim::{Canonicalization, Signature},
+        dmarc::Dmarc,
+        spf::Spf,
+    };
+
+    #[test]
+    fn serde_roundtrip() {
+        let spf = Spf::parse(
+            concat!(
+                "v=spf1 ip4:192.0.2.0/24 -ip6:2001:db8::/32 a:mx.%{d}/30//64 ",
+                "~mx include:_spf.example.org exists:%{ir}.%{l1r+-}._spf.%{d} ",
+                "?all exp=explain._spf.%{d}"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let json = serde_json::to_string(&spf).unwrap();
+        for expected in [
+            r#"{"Ip4":{"addr":"192.0.2.0","cidr_length":24}}"#,
+            r#"{"Ip6":{"addr":"2001:db8::","cidr_length":32}}"#,
+            r#""ip4_cidr_length":30,"ip6_cidr_length":64"#,
+            r#""delimiters":"-+""#,
+            r#"{"Literal":"._spf."}"#,
+        ] {
+            assert!(json.contains(expected), "{expected} not found in {json}");
+        }
+        assert_eq!(serde_json::from_str::<Spf>(&json).unwrap(), spf);
+
+        let signature = Signature {
+            a: Algorithm::Ed25519Sha256,
+            d: "example.org".to_string(),
+            s: "selector".to_string(),
+            h: vec!["From".to_string(), "Subject".to_string()],
+            t: 1234,
+            ch: Canonicalization::Simple,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(serde_json::from_str::<Signature>(&json).unwrap(), signature);
+
+        let dmarc = Dmarc::parse(
+            b"v=DMARC1; p=reject; sp=quarantine; adkim=s; rua=mailto:dmarc@example.org",
+        )
+        .unwrap();
+        let json = serde_json::to_string(&dmarc).unwrap();
+        assert_eq!(serde_json::from_str::<Dmarc>(&json).unwrap(), dmarc);
+    }
+}
@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use super::{Mechanism, Modifier, Qualifier, Spf, Warning};
+
+// RFC 7208 section 3.4: records should be kept small enough for the
+// response to fit in a single 512-octet UDP packet.
+const MAX_RECORD_LEN: usize = 450;
+const MAX_LOOKUPS: usize = 10;
+// RFC 7208 section 4.6.4: each mx and ptr mechanism may query up to ten
+// further names, so more than a couple of them compounds the lookup risk.
+const MAX_MX_PTR: usize = 2;
+
+impl Spf {
+    /// Lints the record, returning the problems that are not parse errors
+    /// but may cause it to fail or behave unexpectedly during evaluation.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+
+        if self.lookup_terms().count() > MAX_LOOKUPS {
+            warnings.push(Warning::TooManyLookups);
+        }
+
+        let record_len = self.to_string().len();
+        if record_len > MAX_RECORD_LEN {
+            warnings.push(Warning::RecordTooLong(record_len));
+        }
+
+        if self
+            .directives
+            .iter()
+            .any(|d| matches!(d.mechanism, Mechanism::Ptr { .. }))
+        {
+            warnings.push(Warning::DeprecatedPtr);
+        }
+
+        let mx_ptr = self
+            .directives
+            .iter()
+            .filter(|d| matches!(d.mechanism, Mechanism::Mx { .. } | Mechanism::Ptr { .. }))
+            .count();
+        if mx_ptr > MAX_MX_PTR {
+            warnings.push(Warning::ManyMxPtr(mx_ptr));
+        }
+
+        for Modifier::Unknown(name, _) in &self.modifiers {
+            warnings.push(Warning::UnknownModifier(name.clone()));
+        }
+
+        match self
+            .directives
+            .iter()
+            .find(|d| d.mechanism == Mechanism::All)
+        {
+            Some(directive) if directive.qualifier == Qualifier::Pass => {
+                warnings.push(Warning::PassAll);
+            }
+            Some(_) => (),
+            None if self.redirect.is_none() => {
+                warnings.push(Warning::MissingAll);
+            }
+            None => (),
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::parse::TxtRecordParser,
+        spf::{Spf, Warning},
+    };
+
+    #[test]
+    fn spf_validate() {
+        let long_record = format!(
+            "v=spf1 {} -all",
+            (0..30)
+                .map(|n| format!("ip4:192.0.2.{n}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let many_lookups = format!(
+            "v=spf1 {} -all",
+            (0..11)
+                .map(|n| format!("include:_spf{n}.example.org"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        for (record, expected) in [
+            ("v=spf1 ip4:192.0.2.0/24 -all", vec![]),
+            ("v=spf1 redirect=_spf.example.org", vec![]),
+            ("v=spf1 ip4:192.0.2.0/24 +all", vec![Warning::PassAll]),
+            ("v=spf1 ptr:example.org ~all", vec![Warning::DeprecatedPtr]),
+            ("v=spf1 ip4:192.0.2.0/24", vec![Warning::MissingAll]),
+            (
+                "v=spf1 ptr all",
+                vec![Warning::DeprecatedPtr, Warning::PassAll],
+            ),
+            (
+                long_record.as_str(),
+                vec![Warning::RecordTooLong(long_record.len())],
+            ),
+            (many_lookups.as_str(), vec![Warning::TooManyLookups]),
+            ("v=spf1 mx mx:example.org -all", vec![]),
+            (
+                "v=spf1 mx mx:example.org mx:example.net/24 -all",
+                vec![Warning::ManyMxPtr(3)],
+            ),
+            (
+                "v=spf1 mx ptr mx:example.org -all",
+                vec![Warning::DeprecatedPtr, Warning::ManyMxPtr(3)],
+            ),
+            (
+                "v=spf1 mx foo=bar -all",
+                vec![Warning::UnknownModifier("foo".to_string())],
+            ),
+        ] {
+            assert_eq!(
+                Spf::parse(record.as_bytes()).unwrap().validate(),
+                expected,
+                "{record}"
+            );
+        }
+    }
+}
@@ -9,13 +9,22 @@
  */
 
 use std::{
+    future::{poll_fn, Future},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    time::Instant,
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::{Error, Resolver, SpfOutput, SpfResult};
+use crate::{
+    common::{lru::DnsCache, resolver::IntoFqdn},
+    Error, Resolver, SpfOutput, SpfResult,
+};
 
-use super::{Macro, Mechanism, Qualifier, Spf, Variables};
+use super::{
+    Directive, Macro, Mechanism, Qualifier, Spf, SpfContext, SpfRequest, TraceStep, Variables,
+    BATCH_CONCURRENCY, EVALUATION_TIMEOUT,
+};
 
 #[allow(clippy::iter_skip_zero)]
 impl Resolver {
@@ -36,7 +45,9 @@ impl Resolver {
             )
             .await
         } else {
-            SpfOutput::new(helo_domain.to_string()).with_result(SpfResult::None)
+            SpfOutput::new(helo_domain.to_string())
+                .with_ip(ip)
+                .with_result(SpfResult::None)
         }
     }
 
@@ -58,6 +69,27 @@ impl Resolver {
         .await
     }
 
+    /// Verifies the SPF MAIL FROM identity at the given UNIX time, which is
+    /// what the `%{t}` macro expands to.
+    pub async fn verify_spf_sender_at(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        now: u64,
+    ) -> SpfOutput {
+        self.check_host_at(
+            ip,
+            sender.rsplit_once('@').map_or(helo_domain, |(_, d)| d),
+            helo_domain,
+            host_domain,
+            sender,
+            now,
+        )
+        .await
+    }
+
     /// Verifies both the SPF EHLO and MAIL FROM identities
     pub async fn verify_spf(
         &self,
@@ -77,42 +109,189 @@ impl Resolver {
         }
     }
 
+    /// Verifies the SPF EHLO and MAIL FROM identities of several connections
+    /// concurrently, sharing the DNS cache between them. At most
+    /// [`BATCH_CONCURRENCY`] checks are in progress at any time and the
+    /// results are returned in the order of the requests.
+    pub async fn verify_spf_batch(&self, requests: Vec<SpfRequest>) -> Vec<SpfOutput> {
+        let mut results: Vec<Option<SpfOutput>> = vec![None; requests.len()];
+        let mut requests = requests.into_iter().enumerate();
+        let mut pending: Vec<(usize, SpfCheck<'_>)> = Vec::with_capacity(BATCH_CONCURRENCY);
+
+        poll_fn(|cx| loop {
+            while pending.len() < BATCH_CONCURRENCY {
+                if let Some((pos, request)) = requests.next() {
+                    pending.push((
+                        pos,
+                        Box::pin(async move {
+                            self.verify_spf(
+                                request.ip,
+                                &request.helo_domain,
+                                &request.host_domain,
+                                &request.mail_from,
+                            )
+                            .await
+                        }),
+                    ));
+                } else {
+                    break;
+                }
+            }
+            if pending.is_empty() {
+                return Poll::Ready(());
+            }
+
+            let in_progress = pending.len();
+            pending.retain_mut(|(pos, check)| match check.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    results[*pos] = Some(output);
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if pending.len() == in_progress {
+                return Poll::Pending;
+            }
+        })
+        .await;
+
+        results.into_iter().flatten().collect()
+    }
+
+    /// Verifies the SPF MAIL FROM identity, returning the mechanisms evaluated
+    /// in order along with the result.
+    pub async fn verify_spf_sender_traced(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+    ) -> (SpfOutput, Vec<TraceStep>) {
+        let mut trace = Some(Vec::new());
+        let output = self
+            .check_host_(
+                ip,
+                sender.rsplit_once('@').map_or(helo_domain, |(_, d)| d),
+                helo_domain,
+                host_domain,
+                sender,
+                unix_now(),
+                &mut trace,
+            )
+            .await;
+        (output, trace.unwrap_or_default())
+    }
+
+    /// The `check_host()` function of RFC 7208 section 4, evaluating the SPF
+    /// record of `domain` for `ip` and the identity `sender`.
+    pub async fn check_host(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+    ) -> SpfOutput {
+        self.check_host_at(ip, domain, helo_domain, host_domain, sender, unix_now())
+            .await
+    }
+
+    /// Evaluates the SPF record of a domain at the given UNIX time, which is
+    /// what the `%{t}` macro expands to.
+    pub async fn check_host_at(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        now: u64,
+    ) -> SpfOutput {
+        self.check_host_(ip, domain, helo_domain, host_domain, sender, now, &mut None)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn check_host_(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        now: u64,
+        trace: &mut Option<Vec<TraceStep>>,
+    ) -> SpfOutput {
+        if let Some(mechanism) = self
+            .spf_trusted_forwarders
+            .iter()
+            .find(|mechanism| mechanism.matches_ip(ip))
+        {
+            return SpfOutput::new(domain.to_string())
+                .with_ip(ip)
+                .with_trusted_forwarder(mechanism);
+        }
+
+        let mut expires = None;
+        self.evaluate(
+            ip,
+            domain,
+            helo_domain,
+            host_domain,
+            sender,
+            now,
+            trace,
+            &mut expires,
+        )
+        .await
+        .with_valid_until(expires)
+    }
+
+    // Evaluates a record, keeping in `expires` the earliest expiration of
+    // the DNS records consulted.
     #[allow(clippy::while_let_on_iterator)]
     #[allow(clippy::iter_skip_zero)]
-    pub async fn check_host(
+    #[allow(clippy::too_many_arguments)]
+    async fn evaluate(
         &self,
         ip: IpAddr,
         domain: &str,
         helo_domain: &str,
         host_domain: &str,
         sender: &str,
+        now: u64,
+        trace: &mut Option<Vec<TraceStep>>,
+        expires: &mut Option<Instant>,
     ) -> SpfOutput {
-        let output = SpfOutput::new(domain.to_string());
+        let output = SpfOutput::new(domain.to_string()).with_ip(ip);
         if domain.is_empty() || domain.len() > 63 || !domain.has_labels() {
             return output.with_result(SpfResult::None);
         }
-        let mut vars = Variables::new();
+        let context = SpfContext::from_sender(sender, ip, helo_domain, domain);
+        let mut vars = context.variables();
         let mut has_p_var = false;
-        vars.set_ip(&ip);
-        if !sender.is_empty() {
-            vars.set_sender(sender.as_bytes());
-        } else {
-            vars.set_sender(format!("postmaster@{domain}").into_bytes());
-        }
-        vars.set_domain(domain.as_bytes());
         vars.set_host_domain(host_domain.as_bytes());
-        vars.set_helo_domain(helo_domain.as_bytes());
+        vars.set_current_time(now);
 
-        let mut lookup_limit = LookupLimit::new();
+        let mut lookup_limit = LookupLimit::new(self.spf_timeout);
         let mut spf_record = match self.txt_lookup::<Spf>(domain).await {
             Ok(spf_record) => spf_record,
             Err(err) => return output.with_result(err.into()),
         };
+        expire_at(
+            expires,
+            self.cache_txt.valid_until(domain.into_fqdn().as_ref()),
+        );
 
         let mut domain = domain.to_string();
         let mut include_stack = Vec::new();
+        let mut include_trace = Vec::new();
+
+        // Domains in the chain of includes and redirects being evaluated
+        let mut visited = vec![domain.trim_end_matches('.').to_ascii_lowercase()];
 
         let mut result = None;
+        let mut matched = None;
         let mut directives = spf_record.directives.iter().enumerate().skip(0);
 
         loop {
@@ -120,24 +299,24 @@ impl Resolver {
                 if !has_p_var && directive.mechanism.needs_ptr() {
                     if !lookup_limit.can_lookup() {
                         return output
-                            .with_result(SpfResult::PermError)
+                            .with_result(lookup_limit.exceeded_result())
                             .with_report(&spf_record);
                     }
-                    if let Some(ptr) = self
-                        .ptr_lookup(ip)
-                        .await
-                        .ok()
-                        .and_then(|ptrs| ptrs.first().map(|ptr| ptr.as_bytes().to_vec()))
-                    {
-                        vars.set_validated_domain(ptr);
-                    }
+                    vars.set_validated_domain(
+                        self.validated_domain(ip, &domain, expires)
+                            .await
+                            .into_bytes(),
+                    );
                     has_p_var = true;
                 }
 
+                // DNS answers seen while evaluating the mechanism, for tracing
+                let mut answers = Vec::new();
                 let matches = match &directive.mechanism {
                     Mechanism::All => true,
-                    Mechanism::Ip4 { addr, mask } => ip.matches_ipv4_mask(addr, *mask),
-                    Mechanism::Ip6 { addr, mask } => ip.matches_ipv6_mask(addr, *mask),
+                    Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. } => {
+                        directive.mechanism.matches_ip(ip)
+                    }
                     Mechanism::A {
                         macro_string,
                         ip4_mask,
@@ -145,15 +324,29 @@ impl Resolver {
                     } => {
                         if !lookup_limit.can_lookup() {
                             return output
-                                .with_result(SpfResult::PermError)
+                                .with_result(lookup_limit.exceeded_result())
                                 .with_report(&spf_record);
                         }
+                        let target_name = match macro_string.eval_domain(
+                            &vars,
+                            &domain,
+                            self.spf_max_expansion,
+                        ) {
+                            Some(target_name) => target_name,
+                            None => {
+                                return output
+                                    .with_result(SpfResult::PermError)
+                                    .with_report(&spf_record);
+                            }
+                        };
                         match self
                             .ip_matches(
-                                macro_string.eval(&vars, &domain, true).as_ref(),
+                                target_name.as_ref(),
                                 ip,
                                 *ip4_mask,
                                 *ip6_mask,
+                                expires,
+                                trace.is_some().then_some(&mut answers),
                             )
                             .await
                         {
@@ -173,24 +366,56 @@ impl Resolver {
                     } => {
                         if !lookup_limit.can_lookup() {
                             return output
-                                .with_result(SpfResult::PermError)
+                                .with_result(lookup_limit.exceeded_result())
                                 .with_report(&spf_record);
                         }
 
+                        let target_name = match macro_string.eval_domain(
+                            &vars,
+                            &domain,
+                            self.spf_max_expansion,
+                        ) {
+                            Some(target_name) => target_name,
+                            None => {
+                                return output
+                                    .with_result(SpfResult::PermError)
+                                    .with_report(&spf_record);
+                            }
+                        };
                         let mut matches = false;
-                        match self
-                            .mx_lookup(macro_string.eval(&vars, &domain, true).as_ref())
-                            .await
-                        {
+                        match self.mx_lookup(target_name.as_ref()).await {
                             Ok(records) => {
-                                for exchange in records.iter().flat_map(|mx| mx.exchanges.iter()) {
-                                    if !lookup_limit.can_lookup() {
+                                expire_at(
+                                    expires,
+                                    self.cache_mx.valid_until(target_name.into_fqdn().as_ref()),
+                                );
+                                if trace.is_some() {
+                                    answers.extend(
+                                        records.iter().flat_map(|mx| mx.exchanges.iter().cloned()),
+                                    );
+                                }
+                                // The mx mechanism counts as a single lookup, but it must not
+                                // resolve more than 10 exchanges (RFC 7208 section 4.6.4).
+                                for (num_exchange, exchange) in records
+                                    .iter()
+                                    .flat_map(|mx| mx.exchanges.iter())
+                                    .enumerate()
+                                {
+                                    if num_exchange == MAX_MX_EXCHANGES {
                                         return output
                                             .with_result(SpfResult::PermError)
                                             .with_report(&spf_record);
+                                    } else if lookup_limit.has_expired() {
+                                        return output
+                                            .with_result(SpfResult::TempError)
+                                            .with_report(&spf_record);
                                     }
 
-                                    match self.ip_matches(exchange, ip, *ip4_mask, *ip6_mask).await
+                                    match self
+                                        .ip_matches(
+                                            exchange, ip, *ip4_mask, *ip6_mask, expires, None,
+                                        )
+                                        .await
                                     {
                                         Ok(true) => {
                                             matches = true;
@@ -217,19 +442,52 @@ impl Resolver {
                     Mechanism::Include { macro_string } => {
                         if !lookup_limit.can_lookup() {
                             return output
-                                .with_result(SpfResult::PermError)
+                                .with_result(lookup_limit.exceeded_result())
                                 .with_report(&spf_record);
                         }
 
-                        let target_name = macro_string.eval(&vars, &domain, true);
+                        let target_name = match macro_string.eval_domain(
+                            &vars,
+                            &domain,
+                            self.spf_max_expansion,
+                        ) {
+                            Some(target_name) => target_name,
+                            None => {
+                                return output
+                                    .with_result(SpfResult::PermError)
+                                    .with_report(&spf_record);
+                            }
+                        };
+                        if is_visited(&visited, &target_name) {
+                            return output
+                                .with_result(SpfResult::PermError)
+                                .with_report(&spf_record);
+                        }
                         match self.txt_lookup::<Spf>(target_name.as_ref()).await {
                             Ok(included_spf) => {
+                                expire_at(
+                                    expires,
+                                    self.cache_txt.valid_until(target_name.into_fqdn().as_ref()),
+                                );
+                                if let Some(trace) = trace {
+                                    include_trace.push(trace.len());
+                                    trace.push(trace_step(
+                                        directive,
+                                        &vars,
+                                        &domain,
+                                        include_stack.len(),
+                                        false,
+                                        Vec::new(),
+                                    ));
+                                }
                                 let new_domain = target_name.to_string();
                                 include_stack.push((
                                     std::mem::replace(&mut spf_record, included_spf),
                                     pos,
                                     domain,
+                                    visited.len(),
                                 ));
+                                visited.push(new_domain.trim_end_matches('.').to_ascii_lowercase());
                                 directives = spf_record.directives.iter().enumerate().skip(0);
                                 domain = new_domain;
                                 vars.set_domain(domain.as_bytes().to_vec());
@@ -238,7 +496,9 @@ impl Resolver {
                             Err(
                                 Error::DnsRecordNotFound(_)
                                 | Error::InvalidRecordType
-                                | Error::ParseError,
+                                | Error::ParseError
+                                | Error::InvalidCharacter
+                                | Error::InvalidMacro,
                             ) => {
                                 return output
                                     .with_result(SpfResult::PermError)
@@ -254,19 +514,35 @@ impl Resolver {
                     Mechanism::Ptr { macro_string } => {
                         if !lookup_limit.can_lookup() {
                             return output
-                                .with_result(SpfResult::PermError)
+                                .with_result(lookup_limit.exceeded_result())
                                 .with_report(&spf_record);
                         }
 
-                        let target_addr = macro_string.eval(&vars, &domain, true).to_lowercase();
+                        let target_addr = match macro_string.eval_domain(
+                            &vars,
+                            &domain,
+                            self.spf_max_expansion,
+                        ) {
+                            Some(target_addr) => target_addr.to_lowercase(),
+                            None => {
+                                return output
+                                    .with_result(SpfResult::PermError)
+                                    .with_report(&spf_record);
+                            }
+                        };
                         let target_sub_addr = format!(".{target_addr}");
                         let mut matches = false;
 
                         if let Ok(records) = self.ptr_lookup(ip).await {
+                            expire_at(expires, self.cache_ptr.valid_until(&ip));
+                            if trace.is_some() {
+                                answers.extend(records.iter().cloned());
+                            }
                             for record in records.iter() {
                                 if lookup_limit.can_lookup() {
-                                    if let Ok(true) =
-                                        self.ip_matches(record, ip, u32::MAX, u128::MAX).await
+                                    if let Ok(true) = self
+                                        .ip_matches(record, ip, u32::MAX, u128::MAX, expires, None)
+                                        .await
                                     {
                                         matches = record == &target_addr
                                             || record
@@ -285,14 +561,28 @@ impl Resolver {
                     Mechanism::Exists { macro_string } => {
                         if !lookup_limit.can_lookup() {
                             return output
-                                .with_result(SpfResult::PermError)
+                                .with_result(lookup_limit.exceeded_result())
                                 .with_report(&spf_record);
                         }
 
-                        if let Ok(result) = self
-                            .exists(macro_string.eval(&vars, &domain, true).as_ref())
-                            .await
-                        {
+                        let target_name = match macro_string.eval_domain(
+                            &vars,
+                            &domain,
+                            self.spf_max_expansion,
+                        ) {
+                            Some(target_name) => target_name,
+                            None => {
+                                return output
+                                    .with_result(SpfResult::PermError)
+                                    .with_report(&spf_record);
+                            }
+                        };
+                        if let Ok(result) = self.exists(target_name.as_ref()).await {
+                            expire_at(
+                                expires,
+                                self.cache_ipv4
+                                    .valid_until(target_name.into_fqdn().as_ref()),
+                            );
                             result
                         } else {
                             return output
@@ -302,106 +592,269 @@ impl Resolver {
                     }
                 };
 
+                if let Some(trace) = trace {
+                    trace.push(trace_step(
+                        directive,
+                        &vars,
+                        &domain,
+                        include_stack.len(),
+                        matches,
+                        answers,
+                    ));
+                }
+
                 if matches {
                     result = Some((&directive.qualifier).into());
+                    matched = (directive.mechanism != Mechanism::All).then(|| directive.clone());
                     break;
                 }
             }
 
-            if let Some((prev_record, prev_pos, prev_domain)) = include_stack.pop() {
+            // Follow redirect, which is ignored when the record has an "all"
+            // mechanism (RFC 7208 section 6.1)
+            if let (Some(macro_string), None, None) =
+                (&spf_record.redirect, &result, spf_record.all_qualifier())
+            {
+                if !lookup_limit.can_lookup() {
+                    return output
+                        .with_result(lookup_limit.exceeded_result())
+                        .with_report(&spf_record);
+                }
+
+                let target_name =
+                    match macro_string.eval_domain(&vars, &domain, self.spf_max_expansion) {
+                        Some(target_name) => target_name,
+                        None => {
+                            return output
+                                .with_result(SpfResult::PermError)
+                                .with_report(&spf_record);
+                        }
+                    };
+                if is_visited(&visited, &target_name) {
+                    return output
+                        .with_result(SpfResult::PermError)
+                        .with_report(&spf_record);
+                }
+                match self.txt_lookup::<Spf>(target_name.as_ref()).await {
+                    Ok(redirect_spf) => {
+                        expire_at(
+                            expires,
+                            self.cache_txt.valid_until(target_name.into_fqdn().as_ref()),
+                        );
+                        let new_domain = target_name.to_string();
+                        visited.push(new_domain.trim_end_matches('.').to_ascii_lowercase());
+                        spf_record = redirect_spf;
+                        directives = spf_record.directives.iter().enumerate().skip(0);
+                        domain = new_domain;
+                        vars.set_domain(domain.as_bytes().to_vec());
+                        continue;
+                    }
+                    Err(
+                        Error::DnsRecordNotFound(_)
+                        | Error::InvalidRecordType
+                        | Error::ParseError
+                        | Error::InvalidCharacter
+                        | Error::InvalidMacro,
+                    ) => {
+                        return output
+                            .with_result(SpfResult::PermError)
+                            .with_report(&spf_record)
+                    }
+                    Err(_) => {
+                        return output
+                            .with_result(SpfResult::TempError)
+                            .with_report(&spf_record)
+                    }
+                }
+            }
+
+            if let Some((prev_record, prev_pos, prev_domain, prev_visited)) = include_stack.pop() {
+                visited.truncate(prev_visited);
                 spf_record = prev_record;
                 directives = spf_record.directives.iter().enumerate().skip(prev_pos);
                 let (_, directive) = directives.next().unwrap();
+                vars.set_domain(prev_domain.as_bytes().to_vec());
+                domain = prev_domain;
+
+                if let (Some(trace), Some(step)) = (trace.as_mut(), include_trace.pop()) {
+                    trace[step].matched = matches!(result, Some(SpfResult::Pass));
+                }
 
                 if matches!(result, Some(SpfResult::Pass)) {
+                    // The include mechanism matched, which ends the evaluation
+                    // of the including record with the include's qualifier.
                     result = Some((&directive.qualifier).into());
-                    break;
+                    matched = Some(directive.clone());
+                    directives = spf_record.directives.iter().enumerate().skip(usize::MAX);
                 } else {
-                    vars.set_domain(prev_domain.as_bytes().to_vec());
-                    domain = prev_domain;
                     result = None;
+                    matched = None;
                 }
             } else {
-                // Follow redirect
-                if let (Some(macro_string), None) = (&spf_record.redirect, &result) {
-                    if !lookup_limit.can_lookup() {
-                        return output
-                            .with_result(SpfResult::PermError)
-                            .with_report(&spf_record);
-                    }
-
-                    let target_name = macro_string.eval(&vars, &domain, true);
-                    match self.txt_lookup::<Spf>(target_name.as_ref()).await {
-                        Ok(redirect_spf) => {
-                            let new_domain = target_name.to_string();
-                            spf_record = redirect_spf;
-                            directives = spf_record.directives.iter().enumerate().skip(0);
-                            domain = new_domain;
-                            vars.set_domain(domain.as_bytes().to_vec());
-                            continue;
-                        }
-                        Err(
-                            Error::DnsRecordNotFound(_)
-                            | Error::InvalidRecordType
-                            | Error::ParseError,
-                        ) => {
-                            return output
-                                .with_result(SpfResult::PermError)
-                                .with_report(&spf_record)
-                        }
-                        Err(_) => {
-                            return output
-                                .with_result(SpfResult::TempError)
-                                .with_report(&spf_record)
-                        }
-                    }
-                }
-
                 break;
             }
         }
 
-        // Evaluate explain
+        // Fetch the explanation only on a Fail, it does not count towards the
+        // lookup limit (RFC 7208 section 6.2)
         if let (Some(macro_string), Some(SpfResult::Fail { .. })) = (&spf_record.exp, &result) {
-            if let Ok(macro_string) = self
-                .txt_lookup::<Macro>(macro_string.eval(&vars, &domain, true).to_string())
-                .await
+            if let Some(Ok(macro_string)) =
+                match macro_string.eval_with_limit(&vars, &domain, true, self.spf_max_expansion) {
+                    Some(target_name) => {
+                        let explanation = self.txt_lookup::<Macro>(target_name.as_ref()).await;
+                        if explanation.is_ok() {
+                            expire_at(
+                                expires,
+                                self.cache_txt.valid_until(target_name.into_fqdn().as_ref()),
+                            );
+                        }
+                        explanation.into()
+                    }
+                    None => None,
+                }
             {
                 return output
                     .with_result(SpfResult::Fail)
                     .with_explanation(macro_string.eval(&vars, &domain, false).to_string())
+                    .with_matched(matched)
+                    .with_policy_hint(&spf_record)
                     .with_report(&spf_record);
             }
         }
 
         output
             .with_result(result.unwrap_or(SpfResult::Neutral))
+            .with_matched(matched)
+            .with_policy_hint(&spf_record)
             .with_report(&spf_record)
     }
 
+    /// Obtains the validated domain name of `ip` that the `%{p}` macro expands to
+    /// (RFC 7208 section 7.3): a PTR name that resolves back to `ip`, preferring
+    /// `domain` or one of its subdomains, or `unknown` if none could be validated.
+    async fn validated_domain(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        expires: &mut Option<Instant>,
+    ) -> String {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let sub_domain = format!(".{domain}");
+        let mut validated = None;
+
+        if let Ok(records) = self.ptr_lookup(ip).await {
+            expire_at(expires, self.cache_ptr.valid_until(&ip));
+            for record in records.iter().take(10) {
+                if let Ok(true) = self
+                    .ip_matches(record, ip, u32::MAX, u128::MAX, expires, None)
+                    .await
+                {
+                    let name = record.trim_end_matches('.').to_lowercase();
+                    if name == domain || name.ends_with(&sub_domain) {
+                        return name;
+                    } else if validated.is_none() {
+                        validated = Some(name);
+                    }
+                }
+            }
+        }
+
+        validated.unwrap_or_else(|| "unknown".to_string())
+    }
+
     async fn ip_matches(
         &self,
         target_name: &str,
         ip: IpAddr,
         ip4_mask: u32,
         ip6_mask: u128,
+        expires: &mut Option<Instant>,
+        answers: Option<&mut Vec<String>>,
     ) -> crate::Result<bool> {
-        Ok(match ip {
-            IpAddr::V4(ip) => self
-                .ipv4_lookup(target_name)
-                .await?
-                .iter()
-                .any(|addr| ip.matches_ipv4_mask(addr, ip4_mask)),
-            IpAddr::V6(ip) => self
-                .ipv6_lookup(target_name)
-                .await?
-                .iter()
-                .any(|addr| ip.matches_ipv6_mask(addr, ip6_mask)),
+        // An IPv4-mapped IPv6 client is matched against the A records
+        // of the target, as it would be had it connected over IPv4.
+        Ok(match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let addrs = self.ipv4_lookup(target_name).await?;
+                expire_at(
+                    expires,
+                    self.cache_ipv4
+                        .valid_until(target_name.into_fqdn().as_ref()),
+                );
+                if let Some(answers) = answers {
+                    answers.extend(addrs.iter().map(|addr| addr.to_string()));
+                }
+                addrs
+                    .iter()
+                    .any(|addr| ip.matches_ipv4_mask(addr, ip4_mask))
+            }
+            IpAddr::V6(ip) => {
+                let addrs = self.ipv6_lookup(target_name).await?;
+                expire_at(
+                    expires,
+                    self.cache_ipv6
+                        .valid_until(target_name.into_fqdn().as_ref()),
+                );
+                if let Some(answers) = answers {
+                    answers.extend(addrs.iter().map(|addr| addr.to_string()));
+                }
+                addrs
+                    .iter()
+                    .any(|addr| ip.matches_ipv6_mask(addr, ip6_mask))
+            }
         })
     }
 }
 
-trait IpMask {
+// Records an evaluated directive along with the DNS answers it was matched against.
+fn trace_step(
+    directive: &Directive,
+    vars: &Variables<'_>,
+    domain: &str,
+    depth: usize,
+    matched: bool,
+    answers: Vec<String>,
+) -> TraceStep {
+    let target = match &directive.mechanism {
+        Mechanism::A { macro_string, .. }
+        | Mechanism::Mx { macro_string, .. }
+        | Mechanism::Ptr { macro_string }
+        | Mechanism::Include { macro_string }
+        | Mechanism::Exists { macro_string } => {
+            Some(macro_string.eval(vars, domain, true).into_owned())
+        }
+        Mechanism::All | Mechanism::Ip4 { .. } | Mechanism::Ip6 { .. } => None,
+    };
+
+    TraceStep {
+        domain: domain.to_string(),
+        depth,
+        directive: directive.clone(),
+        target,
+        answers,
+        matched,
+    }
+}
+
+// Returns `true` if a domain is already part of the chain of includes and
+// redirects being evaluated, which would make evaluation loop.
+fn is_visited(visited: &[String], name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    visited
+        .iter()
+        .any(|domain| domain.eq_ignore_ascii_case(name))
+}
+
+// Keeps the earliest of two expiration times.
+fn expire_at(expires: &mut Option<Instant>, valid_until: Option<Instant>) {
+    if let Some(valid_until) = valid_until {
+        *expires = Some(expires.map_or(valid_until, |expires| expires.min(valid_until)));
+    }
+}
+
+type SpfCheck<'x> = Pin<Box<dyn Future<Output = SpfOutput> + Send + 'x>>;
+
+pub(crate) trait IpMask {
     fn matches_ipv4_mask(&self, addr: &Ipv4Addr, mask: u32) -> bool;
     fn matches_ipv6_mask(&self, addr: &Ipv6Addr, mask: u128) -> bool;
 }
@@ -461,38 +914,64 @@ impl From<&Qualifier> for SpfResult {
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 impl From<Error> for SpfResult {
     fn from(err: Error) -> Self {
         match err {
             Error::DnsRecordNotFound(_) | Error::InvalidRecordType => SpfResult::None,
-            Error::ParseError => SpfResult::PermError,
+            Error::ParseError | Error::InvalidCharacter | Error::InvalidMacro => {
+                SpfResult::PermError
+            }
             _ => SpfResult::TempError,
         }
     }
 }
 
+const MAX_MX_EXCHANGES: usize = 10;
+
 struct LookupLimit {
     num_lookups: u32,
-    timer: Instant,
+    deadline: Instant,
 }
 
 impl LookupLimit {
-    pub fn new() -> Self {
+    pub fn new(timeout: Duration) -> Self {
+        let now = Instant::now();
         LookupLimit {
             num_lookups: 1,
-            timer: Instant::now(),
+            deadline: now.checked_add(timeout).unwrap_or(now + EVALUATION_TIMEOUT),
         }
     }
 
     #[inline(always)]
     fn can_lookup(&mut self) -> bool {
-        if self.num_lookups < 10 && self.timer.elapsed().as_secs() < 20 {
+        if self.num_lookups < 10 && !self.has_expired() {
             self.num_lookups += 1;
             true
         } else {
             false
         }
     }
+
+    #[inline(always)]
+    fn has_expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Result of an evaluation that ran out of lookups or time
+    fn exceeded_result(&self) -> SpfResult {
+        if self.has_expired() {
+            SpfResult::TempError
+        } else {
+            SpfResult::PermError
+        }
+    }
 }
 
 pub trait HasLabels {
@@ -522,6 +1001,8 @@ impl HasLabels for &str {
 mod test {
 
     use std::{
+        borrow::Cow,
+        collections::HashMap,
         fs,
         net::{IpAddr, Ipv4Addr, Ipv6Addr},
         path::PathBuf,
@@ -529,8 +1010,8 @@ mod test {
     };
 
     use crate::{
-        common::parse::TxtRecordParser,
-        spf::{Macro, Spf},
+        common::{parse::TxtRecordParser, resolver::parse_txt_records},
+        spf::{Macro, Mechanism, Spf, SpfPolicyHint, SpfRequest, TraceStep, BATCH_CONCURRENCY},
         Resolver, SpfResult, MX,
     };
 
@@ -554,6 +1035,7 @@ mod test {
                 let mut mail_from = "";
                 let mut client_ip = "127.0.0.1".parse::<IpAddr>().unwrap();
                 let mut test_num = 1;
+                let mut spf_records = HashMap::<&str, Vec<Cow<[u8]>>>::new();
 
                 for line in test.split('\n') {
                     let line = line.trim();
@@ -567,11 +1049,14 @@ mod test {
                     {
                         if let Some(name) = line.strip_prefix("name:") {
                             test_name = name.trim();
+                            spf_records.clear();
                         } else if let Some(record) = line.strip_prefix("spf:") {
                             let (name, record) = record.trim().split_once(' ').unwrap();
+                            let records = spf_records.entry(name.trim()).or_default();
+                            records.push(record.as_bytes().into());
                             resolver.txt_add(
                                 name.trim().to_string(),
-                                Spf::parse(record.as_bytes()),
+                                parse_txt_records::<Spf>(records.clone()),
                                 valid_until,
                             );
                         } else if let Some(record) = line.strip_prefix("exp:") {
@@ -668,4 +1153,933 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn spf_verify_traced() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        let record = Spf::parse(b"v=spf1 ip4:10.0.0.0/8 -all").unwrap();
+        resolver.txt_add("example.org.", record.clone(), valid_until);
+
+        let (output, trace) = resolver
+            .verify_spf_sender_traced(
+                IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)),
+                "mx.example.org",
+                "localdomain.org",
+                "sender@example.org",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(
+            trace,
+            vec![TraceStep {
+                domain: "example.org".to_string(),
+                depth: 0,
+                directive: record.directives[0].clone(),
+                target: None,
+                answers: vec![],
+                matched: true,
+            }]
+        );
+
+        // Included records are traced one level deeper
+        let included = Spf::parse(b"v=spf1 ip4:192.168.0.0/16 -all").unwrap();
+        let record = Spf::parse(b"v=spf1 include:_spf.example.com -all").unwrap();
+        resolver.txt_add("_spf.example.com.", included.clone(), valid_until);
+        resolver.txt_add("example.com.", record.clone(), valid_until);
+
+        let (output, trace) = resolver
+            .verify_spf_sender_traced(
+                IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)),
+                "mx.example.com",
+                "localdomain.org",
+                "sender@example.com",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Fail);
+        assert_eq!(
+            trace
+                .iter()
+                .map(|step| (
+                    step.domain.as_str(),
+                    step.depth,
+                    step.target.as_deref(),
+                    step.matched
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("example.com", 0, Some("_spf.example.com"), false),
+                ("_spf.example.com", 1, None, false),
+                ("_spf.example.com", 1, None, true),
+                ("example.com", 0, None, true),
+            ]
+        );
+
+        // Steps report the answers the evaluation matched against
+        let record = Spf::parse(b"v=spf1 a:a.example.net mx:example.net -all").unwrap();
+        resolver.txt_add("example.net.", record, valid_until);
+        resolver.ipv4_add(
+            "a.example.net.",
+            vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)],
+            valid_until,
+        );
+        resolver.mx_add(
+            "example.net.",
+            vec![MX {
+                exchanges: vec!["mx.example.net.".to_string()],
+                preference: 10,
+            }],
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "mx.example.net.",
+            vec![Ipv4Addr::new(10, 1, 2, 3)],
+            valid_until,
+        );
+
+        let (output, trace) = resolver
+            .verify_spf_sender_traced(
+                IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)),
+                "mx.example.net",
+                "localdomain.org",
+                "sender@example.net",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(
+            trace
+                .iter()
+                .map(|step| (step.answers.clone(), step.matched))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    vec!["192.0.2.1".to_string(), "192.0.2.2".to_string()],
+                    false
+                ),
+                (vec!["mx.example.net.".to_string()], true),
+            ]
+        );
+    }
+
+    #[test]
+    fn spf_matches_ip() {
+        let record = Spf::parse(b"v=spf1 ip4:192.0.2.0/24 ip6:2001:db8:0:1::/64 -all").unwrap();
+        let (ip4, ip6) = (
+            &record.directives[0].mechanism,
+            &record.directives[1].mechanism,
+        );
+
+        for (mechanism, client, expect) in [
+            (ip4, "192.0.2.0", true),
+            (ip4, "192.0.2.255", true),
+            (ip4, "192.0.1.255", false),
+            (ip4, "192.0.3.0", false),
+            (ip4, "::ffff:192.0.2.255", true),
+            (ip4, "2001:db8:0:1::1", false),
+            (ip6, "2001:db8:0:1::", true),
+            (ip6, "2001:db8:0:1:ffff:ffff:ffff:ffff", true),
+            (ip6, "2001:db8::ffff:ffff:ffff:ffff", false),
+            (ip6, "2001:db8:0:2::", false),
+            (ip6, "192.0.2.1", false),
+        ] {
+            assert_eq!(
+                mechanism.matches_ip(client.parse().unwrap()),
+                expect,
+                "{mechanism:?} {client}"
+            );
+        }
+
+        // Resolved addresses are matched using the dual CIDR lengths
+        let record = Spf::parse(b"v=spf1 a/24//64 mx/24//64 -all").unwrap();
+        for mechanism in [
+            &record.directives[0].mechanism,
+            &record.directives[1].mechanism,
+        ] {
+            assert!(!mechanism.matches_ip("192.0.2.1".parse().unwrap()));
+            for (client, resolved, expect) in [
+                ("192.0.2.0", "192.0.2.1", true),
+                ("192.0.2.255", "192.0.2.1", true),
+                ("192.0.3.0", "192.0.2.1", false),
+                ("2001:db8:0:1:ffff:ffff:ffff:ffff", "2001:db8:0:1::1", true),
+                ("2001:db8:0:2::", "2001:db8:0:1::1", false),
+                ("192.0.2.1", "2001:db8:0:1::1", false),
+            ] {
+                assert_eq!(
+                    mechanism
+                        .matches_resolved_ip(client.parse().unwrap(), resolved.parse().unwrap()),
+                    expect,
+                    "{mechanism:?} {client} {resolved}"
+                );
+            }
+        }
+        assert!(!Mechanism::All
+            .matches_resolved_ip("192.0.2.1".parse().unwrap(), "192.0.2.1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn spf_verify_expansion_limit() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 exists:%{l}.%{l}.%{l}.%{l}.%{d} -all").unwrap(),
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "a.a.a.a.example.org.",
+            vec![Ipv4Addr::new(10, 0, 0, 1)],
+            valid_until,
+        );
+
+        let local_part = "a".repeat(60);
+        for (resolver, sender, expect) in [
+            (
+                resolver.clone(),
+                "a@example.org".to_string(),
+                SpfResult::Pass,
+            ),
+            (
+                resolver.clone(),
+                format!("{local_part}@example.org"),
+                SpfResult::PermError,
+            ),
+            (
+                resolver.with_spf_max_expansion(18),
+                "a@example.org".to_string(),
+                SpfResult::PermError,
+            ),
+        ] {
+            assert_eq!(
+                resolver
+                    .verify_spf_sender(ip, "mx.example.org", "localdomain.org", &sender)
+                    .await
+                    .result(),
+                expect,
+                "{sender}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_exists() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 exists:%{l}._spf.%{d} -all").unwrap(),
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "allowed._spf.example.org.",
+            vec![Ipv4Addr::new(127, 0, 0, 2)],
+            valid_until,
+        );
+        resolver.ipv6_add(
+            "aaaa-only._spf.example.org.",
+            vec![Ipv6Addr::LOCALHOST],
+            valid_until,
+        );
+
+        // Only A records are queried, even for IPv6 clients
+        let ip = IpAddr::V6("2001:db8::1".parse().unwrap());
+        for (sender, expect) in [
+            ("allowed@example.org", SpfResult::Pass),
+            ("aaaa-only@example.org", SpfResult::Fail),
+        ] {
+            assert_eq!(
+                resolver
+                    .verify_spf_sender(ip, "mx.example.org", "localdomain.org", sender)
+                    .await
+                    .result(),
+                expect,
+                "{sender}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_check_host() {
+        // RFC 7208 Appendix A.2
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record) in [
+            ("example.com.", "v=spf1 ip4:192.0.2.128/28 -all"),
+            ("example.net.", "v=spf1 ip4:10.0.0.5 -all"),
+            (
+                "example.org.",
+                "v=spf1 include:example.com include:example.net -all",
+            ),
+            ("la.example.org.", "v=spf1 redirect=example.org"),
+            ("ny.example.org.", "v=spf1 redirect=example.org"),
+            ("sf.example.org.", "v=spf1 redirect=example.org"),
+        ] {
+            resolver.txt_add(domain, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+        }
+
+        for (ip, domain, expect) in [
+            ("192.0.2.129", "sf.example.org", SpfResult::Pass),
+            ("10.0.0.5", "la.example.org", SpfResult::Pass),
+            ("10.0.0.4", "ny.example.org", SpfResult::Fail),
+            ("10.0.0.5", "example.net", SpfResult::Pass),
+            ("192.0.2.129", "example.net", SpfResult::Fail),
+            ("192.0.2.129", "unknown.example.org", SpfResult::None),
+        ] {
+            assert_eq!(
+                resolver
+                    .check_host(
+                        ip.parse().unwrap(),
+                        domain,
+                        "mx.example.org",
+                        "localdomain.org",
+                        "tom@sf.example.org",
+                    )
+                    .await
+                    .result(),
+                expect,
+                "{ip} {domain}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_policy_hint() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record) in [
+            ("pass-all.example.org.", "v=spf1 ip4:192.0.2.0/24 +all"),
+            ("neutral-all.example.org.", "v=spf1 ?all"),
+            (
+                "fail-all.example.org.",
+                "v=spf1 include:pass-all.example.org -all",
+            ),
+            (
+                "redirect.example.org.",
+                "v=spf1 redirect=pass-all.example.org",
+            ),
+        ] {
+            resolver.txt_add(domain, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+        }
+
+        for (ip, domain, result, hint) in [
+            (
+                "192.0.2.1",
+                "pass-all.example.org",
+                SpfResult::Pass,
+                Some(SpfPolicyHint::PassAll),
+            ),
+            (
+                "198.51.100.1",
+                "pass-all.example.org",
+                SpfResult::Pass,
+                Some(SpfPolicyHint::PassAll),
+            ),
+            (
+                "198.51.100.1",
+                "neutral-all.example.org",
+                SpfResult::Neutral,
+                Some(SpfPolicyHint::NeutralAll),
+            ),
+            (
+                "198.51.100.1",
+                "redirect.example.org",
+                SpfResult::Pass,
+                Some(SpfPolicyHint::PassAll),
+            ),
+            // Only the terminal all of the evaluated record counts
+            (
+                "198.51.100.1",
+                "fail-all.example.org",
+                SpfResult::Pass,
+                None,
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mx.example.org",
+                    "localdomain.org",
+                    &format!("sender@{domain}"),
+                )
+                .await;
+            assert_eq!(output.result(), result, "{ip} {domain}");
+            assert_eq!(output.policy_hint(), hint, "{ip} {domain}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_helo_macros() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        for helo in ["mx.example.org.", "other.example.org."] {
+            resolver.txt_add(
+                helo,
+                Spf::parse(b"v=spf1 exists:%{l}.%{o}.%{h}._spf.example.org -all").unwrap(),
+                valid_until,
+            );
+        }
+        resolver.ipv4_add(
+            "postmaster.mx.example.org.mx.example.org._spf.example.org.",
+            vec![Ipv4Addr::new(127, 0, 0, 2)],
+            valid_until,
+        );
+
+        // The HELO identity is checked as postmaster@<helo> (RFC 7208 section 2.3)
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        for (helo, expect) in [
+            ("mx.example.org", SpfResult::Pass),
+            ("other.example.org", SpfResult::Fail),
+        ] {
+            assert_eq!(
+                resolver
+                    .verify_spf_helo(ip, helo, "localdomain.org")
+                    .await
+                    .result(),
+                expect,
+                "{helo}"
+            );
+
+            // A null reverse-path also falls back to the HELO identity
+            assert_eq!(
+                resolver
+                    .verify_spf_sender(ip, helo, "localdomain.org", "")
+                    .await
+                    .result(),
+                expect,
+                "{helo}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_matched_directive() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        let record = Spf::parse(b"v=spf1 ip4:192.0.2.0/24 include:_spf.example.org -all").unwrap();
+        resolver.txt_add("example.org.", record.clone(), valid_until);
+        resolver.txt_add(
+            "_spf.example.org.",
+            Spf::parse(b"v=spf1 ip4:198.51.100.0/24 -all").unwrap(),
+            valid_until,
+        );
+
+        for (ip, expected_result, expected_directive) in [
+            (
+                Ipv4Addr::new(192, 0, 2, 10),
+                SpfResult::Pass,
+                Some(&record.directives[0]),
+            ),
+            (
+                Ipv4Addr::new(198, 51, 100, 10),
+                SpfResult::Pass,
+                Some(&record.directives[1]),
+            ),
+            (Ipv4Addr::new(203, 0, 113, 10), SpfResult::Fail, None),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    IpAddr::V4(ip),
+                    "mx.example.org",
+                    "localdomain.org",
+                    "sender@example.org",
+                )
+                .await;
+            assert_eq!(output.result(), expected_result, "{ip}");
+            assert_eq!(output.matched_directive(), expected_directive, "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_ttl() {
+        let now = Instant::now();
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 include:_spf.example.org -all").unwrap(),
+            now + Duration::from_secs(300),
+        );
+        resolver.txt_add(
+            "_spf.example.org.",
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 -all").unwrap(),
+            now + Duration::from_secs(60),
+        );
+
+        let output = resolver
+            .verify_spf_sender(
+                "192.0.2.1".parse().unwrap(),
+                "mx.example.org",
+                "localdomain.org",
+                "sender@example.org",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        let ttl = output.ttl().unwrap();
+        assert!(
+            ttl <= Duration::from_secs(60) && ttl > Duration::from_secs(50),
+            "{ttl:?}"
+        );
+
+        let output = resolver
+            .verify_spf_sender(
+                "192.0.2.1".parse().unwrap(),
+                "mx.example.org",
+                "localdomain.org",
+                "sender@unknown.example.org",
+            )
+            .await;
+        assert_eq!(output.ttl(), None);
+    }
+
+    #[tokio::test]
+    async fn spf_explain() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 -ip4:198.51.100.0/24 -all").unwrap(),
+            valid_until,
+        );
+
+        for (ip, expected) in [
+            (
+                "203.0.113.5",
+                concat!(
+                    "SPF failed for example.org: client 203.0.113.5 not in any ",
+                    "authorized mechanism; record ended with -all"
+                ),
+            ),
+            (
+                "198.51.100.7",
+                "SPF failed for example.org: client 198.51.100.7 matched -ip4:198.51.100.0/24",
+            ),
+            (
+                "192.0.2.1",
+                "SPF passed for example.org: client 192.0.2.1 matched ip4:192.0.2.0/24",
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mx.example.org",
+                    "localdomain.org",
+                    "sender@example.org",
+                )
+                .await;
+            assert_eq!(output.explain(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_at() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 ip4:192.0.2.0/24 -all exp=explain._spf.%{d}").unwrap(),
+            valid_until,
+        );
+        resolver.txt_add(
+            "explain._spf.example.org.",
+            Macro::parse(b"Rejected %{i} at %{t}").unwrap(),
+            valid_until,
+        );
+
+        let output = resolver
+            .verify_spf_sender_at(
+                IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1)),
+                "mx.example.org",
+                "localdomain.org",
+                "sender@example.org",
+                1234567890,
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Fail);
+        assert_eq!(
+            output.explanation(),
+            Some("Rejected 203.0.113.1 at 1234567890")
+        );
+    }
+
+    #[tokio::test]
+    async fn spf_verify_exp_lazy() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        let includes = (0..9)
+            .map(|n| format!("include:_spf{n}.example.org"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(
+                format!("v=spf1 {includes} ip4:192.0.2.0/24 -all exp=explain.%{{d}}").as_bytes(),
+            )
+            .unwrap(),
+            valid_until,
+        );
+        for n in 0..9 {
+            resolver.txt_add(
+                format!("_spf{n}.example.org."),
+                Spf::parse(b"v=spf1 -all").unwrap(),
+                valid_until,
+            );
+        }
+        resolver.mock_txt_add(
+            "explain.example.org.",
+            Macro::parse(b"%{i} is not allowed to send for %{d}").unwrap(),
+            valid_until,
+        );
+
+        // The explanation domain is only queried on a Fail, and the query does
+        // not count towards the lookup limit, which the includes exhaust.
+        for (ip, expected, explanation) in [
+            ("192.0.2.1", SpfResult::Pass, None),
+            (
+                "198.51.100.1",
+                SpfResult::Fail,
+                Some("198.51.100.1 is not allowed to send for example.org"),
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mx.example.org",
+                    "localdomain.org",
+                    "sender@example.org",
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{ip}");
+            assert_eq!(output.explanation(), explanation, "{ip}");
+            assert_eq!(
+                resolver
+                    .mock_queries()
+                    .contains(&"explain.example.org.".to_string()),
+                explanation.is_some(),
+                "{ip}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_trusted_forwarder() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_spf_trusted_forwarder("192.0.2.0".parse().unwrap(), 24)
+            .with_spf_trusted_forwarder("2001:db8::".parse().unwrap(), 32)
+            .with_spf_trusted_forwarder("::ffff:198.18.0.0".parse().unwrap(), 111);
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 ip4:203.0.113.0/24 -all").unwrap(),
+            valid_until,
+        );
+
+        // Queries for "_dns_error." names fail, so trusted forwarders
+        // pass without looking up the record.
+        for (ip, sender, expected, trusted) in [
+            ("192.0.2.10", "sender@example.org", SpfResult::Pass, true),
+            (
+                "::ffff:192.0.2.10",
+                "sender@example.org",
+                SpfResult::Pass,
+                true,
+            ),
+            ("2001:db8::1", "sender@example.org", SpfResult::Pass, true),
+            ("198.19.1.1", "sender@example.org", SpfResult::Pass, true),
+            ("198.20.0.1", "sender@example.org", SpfResult::Fail, false),
+            ("192.0.2.10", "sender@_dns_error.org", SpfResult::Pass, true),
+            ("203.0.113.1", "sender@example.org", SpfResult::Pass, false),
+            ("198.51.100.1", "sender@example.org", SpfResult::Fail, false),
+            ("2001:db9::1", "sender@example.org", SpfResult::Fail, false),
+            (
+                "198.51.100.1",
+                "sender@_dns_error.org",
+                SpfResult::TempError,
+                false,
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mx.example.org",
+                    "localdomain.org",
+                    sender,
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{ip} {sender}");
+            assert_eq!(output.is_trusted_forwarder(), trusted, "{ip} {sender}");
+        }
+
+        let output = resolver
+            .verify_spf_helo(
+                "192.0.2.10".parse().unwrap(),
+                "mx.example.org",
+                "localdomain.org",
+            )
+            .await;
+        assert!(output.is_trusted_forwarder());
+        assert_eq!(
+            output.explain(),
+            concat!(
+                "SPF passed for mx.example.org: client 192.0.2.10 ",
+                "is a trusted forwarder in ip4:192.0.2.0/24"
+            )
+        );
+
+        // A mapped network spanning all IPv4 addresses
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_spf_trusted_forwarder("::ffff:0.0.0.0".parse().unwrap(), 96);
+        for (ip, trusted) in [("198.51.100.1", true), ("2001:db9::1", false)] {
+            let output = resolver
+                .verify_spf_helo(ip.parse().unwrap(), "mx.example.org", "localdomain.org")
+                .await;
+            assert_eq!(output.is_trusted_forwarder(), trusted, "{ip}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn spf_trusted_forwarder_invalid_prefix() {
+        let _ = Resolver::new_system_conf()
+            .unwrap()
+            .with_spf_trusted_forwarder("192.0.2.0".parse().unwrap(), 33);
+    }
+
+    #[tokio::test]
+    async fn spf_verify_ipv4_no_aaaa() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 a:a._dns_error.example.org mx:example.org -all").unwrap(),
+            valid_until,
+        );
+        resolver.mx_add(
+            "example.org.",
+            vec![MX {
+                exchanges: vec!["mx._dns_error.example.org.".to_string()],
+                preference: 10,
+            }],
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "a._dns_error.example.org.",
+            vec![Ipv4Addr::new(192, 0, 2, 1)],
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "mx._dns_error.example.org.",
+            vec![Ipv4Addr::new(192, 0, 2, 2)],
+            valid_until,
+        );
+
+        // Uncached AAAA queries for "_dns_error." names fail, so an IPv4 client
+        // only passes if a and mx never query AAAA records.
+        for (ip, expected) in [
+            ("192.0.2.1", SpfResult::Pass),
+            ("192.0.2.2", SpfResult::Pass),
+            ("192.0.2.3", SpfResult::Fail),
+            ("::ffff:192.0.2.2", SpfResult::Pass),
+            ("2001:db8::1", SpfResult::TempError),
+        ] {
+            assert_eq!(
+                resolver
+                    .verify_spf_sender(
+                        ip.parse().unwrap(),
+                        "mx.example.org",
+                        "localdomain.org",
+                        "sender@example.org",
+                    )
+                    .await
+                    .result(),
+                expected,
+                "{ip}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_timeout() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_mock_latency(Duration::from_millis(100));
+        for (name, record) in [
+            ("test.org.", "v=spf1 include:1.test.org ~all"),
+            ("1.test.org.", "v=spf1 include:2.test.org -all"),
+            ("2.test.org.", "v=spf1 include:3.test.org -all"),
+            ("3.test.org.", "v=spf1 -all"),
+        ] {
+            resolver.mock_txt_add(name, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+        }
+
+        for (resolver, expected) in [
+            (resolver.clone(), SpfResult::SoftFail),
+            (
+                resolver.with_spf_timeout(Duration::from_millis(150)),
+                SpfResult::TempError,
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                    "mx.test.org",
+                    "localdomain.org",
+                    "sender@test.org",
+                )
+                .await;
+            assert_eq!(output.result(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_empty_domain_spec() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.ipv4_add("test.org.", vec![Ipv4Addr::new(10, 0, 0, 1)], valid_until);
+
+        // An empty HELO domain makes %{h} expand to an empty domain-spec
+        for (record, expected) in [
+            ("v=spf1 a:%{h} -all", SpfResult::PermError),
+            ("v=spf1 mx:%{h}. -all", SpfResult::PermError),
+            ("v=spf1 ptr:%{h} -all", SpfResult::PermError),
+            ("v=spf1 exists:%{h} -all", SpfResult::PermError),
+            ("v=spf1 include:%{h} -all", SpfResult::PermError),
+            ("v=spf1 redirect=%{h}", SpfResult::PermError),
+            ("v=spf1 a:%{h}test.org -all", SpfResult::Pass),
+        ] {
+            resolver.txt_add(
+                "test.org.",
+                Spf::parse(record.as_bytes()).unwrap(),
+                valid_until,
+            );
+            let output = resolver
+                .verify_spf_sender(
+                    IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                    "",
+                    "localdomain.org",
+                    "sender@test.org",
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{record}");
+        }
+    }
+
+    #[cfg(feature = "idna")]
+    #[tokio::test]
+    async fn spf_verify_idn() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "xn--mller-kva.de.",
+            Spf::parse("v=spf1 a:mx.%{d} include:_spf.bücher.example -all".as_bytes()).unwrap(),
+            valid_until,
+        );
+        resolver.txt_add(
+            "_spf.xn--bcher-kva.example.",
+            Spf::parse(b"v=spf1 ip4:198.51.100.0/24 -all").unwrap(),
+            valid_until,
+        );
+        resolver.ipv4_add(
+            "mx.xn--mller-kva.de.",
+            vec![Ipv4Addr::new(192, 0, 2, 1)],
+            valid_until,
+        );
+
+        for (ip, expected_result) in [
+            (Ipv4Addr::new(192, 0, 2, 1), SpfResult::Pass),
+            (Ipv4Addr::new(198, 51, 100, 1), SpfResult::Pass),
+            (Ipv4Addr::new(203, 0, 113, 1), SpfResult::Fail),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    IpAddr::V4(ip),
+                    "mx.müller.de",
+                    "localdomain.org",
+                    "info@MÜLLER.de",
+                )
+                .await;
+            assert_eq!(output.result(), expected_result, "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_batch() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let build_resolver = |latency| {
+            let resolver = Resolver::new_system_conf()
+                .unwrap()
+                .with_mock_latency(latency);
+            for (name, record) in [
+                (
+                    "test.org.",
+                    "v=spf1 ip4:10.0.0.0/24 include:_spf.example.org -all",
+                ),
+                ("example.org.", "v=spf1 include:_spf.example.org ~all"),
+                ("_spf.example.org.", "v=spf1 a:mx.example.org"),
+            ] {
+                resolver.mock_txt_add(name, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+            }
+            resolver.ipv4_add(
+                "mx.example.org.",
+                vec![Ipv4Addr::new(192, 168, 1, 1)],
+                valid_until,
+            );
+            resolver
+        };
+
+        // Submit more checks than are evaluated concurrently, alternating
+        // between domains that share the include.
+        let requests = (0..BATCH_CONCURRENCY * 2 + 3)
+            .map(|pos| {
+                let domain = if pos % 2 == 0 {
+                    "test.org"
+                } else {
+                    "example.org"
+                };
+                SpfRequest {
+                    ip: match pos % 3 {
+                        0 => IpAddr::V4(Ipv4Addr::new(10, 0, 0, pos as u8)),
+                        1 => IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+                        _ => IpAddr::V4(Ipv4Addr::new(172, 16, 0, 1)),
+                    },
+                    helo_domain: domain.to_string(),
+                    host_domain: "localdomain.org".to_string(),
+                    mail_from: format!("sender{pos}@{domain}"),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Without latency each check completes before the next one starts,
+        // so every record is fetched once and then served from the cache.
+        let resolver = build_resolver(Duration::ZERO);
+        let outputs = resolver.verify_spf_batch(requests.clone()).await;
+        let mut queries = resolver.mock_queries();
+        queries.sort_unstable();
+        assert_eq!(queries, ["_spf.example.org.", "example.org.", "test.org."]);
+        assert_eq!(outputs.len(), requests.len());
+        for (request, output) in requests.iter().zip(&outputs) {
+            assert_eq!(
+                *output,
+                resolver
+                    .verify_spf(
+                        request.ip,
+                        &request.helo_domain,
+                        &request.host_domain,
+                        &request.mail_from,
+                    )
+                    .await,
+                "{request:?}"
+            );
+        }
+        assert_eq!(resolver.mock_queries().len(), 3);
+
+        // With latency the first checks wait on their lookups together, but
+        // no more than the concurrency limit at once.
+        let resolver = build_resolver(Duration::from_millis(10));
+        assert_eq!(resolver.verify_spf_batch(requests).await, outputs);
+        assert_eq!(resolver.mock_max_in_flight(), BATCH_CONCURRENCY);
+
+        assert!(resolver.verify_spf_batch(vec![]).await.is_empty());
+    }
 }